@@ -0,0 +1,42 @@
+#![allow(clippy::needless_return)]
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pixmoosh::agent::{self, Agent, Dir};
+use pixmoosh::ground::Ground;
+use pixmoosh::maf::Rng;
+use pixmoosh::terrain;
+
+const W: usize = 1920;
+const H: usize = 1080;
+
+fn agents(c: &mut Criterion) {
+    let mut group = c.benchmark_group("agents");
+    for n in [10, 100, 1000] {
+        let mut g = Ground::new(W, H);
+        terrain::generate(&mut g, "hills", 1);
+        let mut rng = Rng::new(2);
+        let species = agent::builtin();
+        let mut agents = Vec::new();
+        for i in 0..n {
+            let x = rng.range(0, W as i32) as f32;
+            let y = rng.range(0, H as i32 / 2) as f32;
+            let s = i % species.len();
+            let mut a = Agent::new(s, &species[s], x, y);
+            if rng.one_in(2) {
+                a.dir = Dir::West;
+            }
+            agents.push(a);
+        }
+        group.bench_function(BenchmarkId::from_parameter(n), |b| b.iter(|| {
+            for a in agents.iter_mut() {
+                for (x, y, c) in a.step(&species[a.species], &g, W, H) {
+                    g.set_cell(x, y, c);
+                }
+            }
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, agents);
+criterion_main!(benches);
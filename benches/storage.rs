@@ -0,0 +1,77 @@
+#![allow(clippy::needless_return)]
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use pixmoosh::ground::{Ground, CellType};
+use pixmoosh::maf::Rng;
+
+const W: usize = 1920;
+const H: usize = 1080;
+
+/// A full screen of sand pouring down, so every cell is awake
+fn pouring() -> Ground {
+    let mut g = Ground::new(W, H);
+    for y in 0..H as i32 / 2 {
+        for x in 0..W as i32 {
+            if (x + y) % 3 != 0 {
+                g.set_cell(x, y, CellType::Sand);
+            }
+        }
+    }
+    return g;
+}
+
+/// Fills the rows from y0 down with `cell`
+fn fill(g: &mut Ground, y0: usize, cell: CellType) {
+    for y in y0..g.h {
+        for x in 0..g.w {
+            g.set_cell(x as i32, y as i32, cell);
+        }
+    }
+}
+
+/// Sand poured over the bottom half, left to come to rest
+fn settled_sand() -> Ground {
+    let mut g = Ground::new(W, H);
+    let mut rng = Rng::new(1);
+    for y in H / 2..H {
+        for x in 0..W {
+            if rng.one_in(2) {
+                g.set_cell(x as i32, y as i32, CellType::Sand);
+            }
+        }
+    }
+    for _ in 0..200 {
+        g.update();
+    }
+    return g;
+}
+
+/// Whole updates of a full screen world. They run with the moved map a bit
+/// per cell, or a byte per cell with `--features moved_bytes`.
+fn storage(c: &mut Criterion) {
+    let layout = if cfg!(feature = "moved_bytes") { "byte" } else { "bit" };
+    let mut group = c.benchmark_group(format!("full screen, moved {} per cell", layout));
+    group.sample_size(20);
+
+    group.bench_function("pouring sand", |b| {
+        b.iter_batched_ref(pouring, |g| g.update(), BatchSize::LargeInput)
+    });
+
+    let mut g = Ground::new(W, H);
+    group.bench_function("empty", |b| b.iter(|| g.update()));
+
+    let mut g = settled_sand();
+    group.bench_function("settled sand", |b| b.iter(|| g.update()));
+
+    let mut g = Ground::new(W, H);
+    fill(&mut g, H / 4, CellType::Water);
+    group.bench_function("full water", |b| b.iter(|| g.update()));
+
+    let mut g = Ground::new(W, H);
+    g.init(0);
+    group.bench_function("init", |b| b.iter(|| g.update()));
+    group.finish();
+}
+
+criterion_group!(benches, storage);
+criterion_main!(benches);
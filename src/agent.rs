@@ -0,0 +1,432 @@
+use std::fs;
+use serde::Deserialize;
+use macroquad::prelude::*;
+use macroquad::experimental::animation::*;
+use crate::ground::{Ground, CellType, GroundChange, Topology, is_solid};
+use crate::maf::one_in;
+use crate::clock::DUSK;
+
+/// Ticks before an agent will eat grass or moss it's standing on
+pub const HUNGRY: u32 = 600;
+
+/// Ticks without a meal before an agent gives up work to look for food,
+/// going at half its speed
+pub const STARVING: u32 = HUNGRY * 3;
+
+/// Where species are loaded from, a .toml file each
+pub const SPECIES_DIR: &str = "res/species";
+
+/// What sets one kind of agent apart from the others, loaded from a file
+/// like res/species/dino.toml
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Species {
+    pub name: String,
+    /// Sprite sheet
+    pub sheet: String,
+    /// Width and height of each frame of the sheet
+    pub frame: [u32; 2],
+    pub idle: Anim,
+    pub walk: Anim,
+    /// How many there are in a new world
+    pub count: usize,
+    /// Cells walked a tick
+    pub speed: f32,
+    /// The cell under the middle of its feet, from the top left of the frame
+    pub foot: [i32; 2],
+    /// Falling with nothing underneath, it drifts back the way it came
+    /// rather than dropping straight down
+    pub drifts: bool,
+    /// Colour it's marked with on the minimap
+    pub marker: [u8; 3],
+    pub odds: Odds,
+    pub footprints: Footprints,
+}
+
+/// A row of a sprite sheet
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Anim {
+    pub row: u32,
+    pub frames: u32,
+    pub fps: u32,
+}
+
+/// Chances of changing what it's doing, as one in however many ticks
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Odds {
+    /// Turning round
+    pub turn: i32,
+    /// Winding down at dusk, and getting up in the morning
+    pub dusk: i32,
+    /// Doing something after standing about
+    pub start: i32,
+    /// Of those, digging rather than walking
+    pub dig: i32,
+    /// Stopping walking or building
+    pub stop: i32,
+    /// Of walkers stopping, standing about rather than building
+    pub rest: i32,
+    /// Of those building, building up rather than bridging across
+    pub build: i32,
+    /// Finishing digging
+    pub dug: i32,
+}
+
+/// Cells it changes working, from its foot. Building and bridging are for
+/// facing east, and mirrored facing west.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Footprints {
+    /// Emptied digging
+    pub dig: Vec<[i32; 2]>,
+    /// Seeds planted in the hole when it's done digging
+    pub plant: Vec<[i32; 2]>,
+    /// Wood laid building up
+    pub build: Vec<[i32; 2]>,
+    /// Wood laid bridging across
+    pub bridge: Vec<[i32; 2]>,
+}
+
+impl Species {
+    /// Reads a species from its file's text
+    pub fn parse(text: &str) -> Result<Species, String> {
+        let s: Species = toml::from_str(text).map_err(|e| e.to_string())?;
+        let o = &s.odds;
+        if [o.turn, o.dusk, o.start, o.dig, o.stop, o.rest, o.build, o.dug].iter().any(|&n| n < 1) {
+            return Err(format!("{}: odds have to be at least one in 1", s.name));
+        }
+        if s.frame.contains(&0) || [&s.idle, &s.walk].iter().any(|a| a.frames == 0 || a.fps == 0) {
+            return Err(format!("{}: frames can't be empty", s.name));
+        }
+        return Ok(s);
+    }
+
+    /// Checks the frames of each animation are all on a sheet this big
+    pub fn fits(&self, width: u32, height: u32) -> Result<(), String> {
+        for (what, a) in [("idle", &self.idle), ("walk", &self.walk)] {
+            if self.frame[0] * a.frames > width || self.frame[1] * (a.row + 1) > height {
+                return Err(format!("{}: {} frames run off the {}x{} sheet {}", self.name, what, width, height, self.sheet));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Middle of the frame, from its top left
+    pub fn centre(&self) -> (f32, f32) {
+        return (self.frame[0] as f32 / 2.0, self.frame[1] as f32 / 2.0);
+    }
+
+    pub fn marker(&self) -> Color {
+        return Color::from_rgba(self.marker[0], self.marker[1], self.marker[2], 255);
+    }
+}
+
+/// Loads every species in a directory, in order of file name
+pub fn load(dir: &str) -> Result<Vec<Species>, String> {
+    let mut paths: Vec<_> = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir, e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|x| x == "toml"))
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("{}: no species", dir));
+    }
+    return paths.iter().map(|p| {
+        let text = fs::read_to_string(p).map_err(|e| format!("{}: {}", p.display(), e))?;
+        return Species::parse(&text).map_err(|e| format!("{}: {}", p.display(), e));
+    }).collect();
+}
+
+/// The species that come with the game, for when they can't be loaded
+pub fn builtin() -> Vec<Species> {
+    return [include_str!("../res/species/dino.toml"), include_str!("../res/species/person.toml")]
+        .iter()
+        .map(|text| Species::parse(text).expect("built-in species"))
+        .collect();
+}
+
+pub struct Agent {
+    /// Index into the species list
+    pub species: usize,
+    pub x: f32,
+    pub y: f32,
+    pub vy: f32,
+    /// Ticks since the last meal
+    pub hunger: u32,
+    pub dir: Dir,
+    pub job: Job,
+    pub sprite: AnimatedSprite,
+}
+
+#[derive(PartialEq, Eq)]
+pub enum Dir {
+    North,
+    East,
+    South,
+    West
+}
+
+#[derive(PartialEq, Eq)]
+pub enum Job {
+    Idle,
+    Walk,
+    Build,
+    Bridge,
+    Dig,
+    Sleep,
+}
+
+impl Dir {
+    pub fn op(&self) -> Dir {
+        match self {
+            Dir::North => Dir::South,
+            Dir::East => Dir::West,
+            Dir::South => Dir::North,
+            Dir::West => Dir::East,
+        }
+    }
+}
+
+impl Agent {
+    pub fn new(species: usize, kind: &Species, x: f32, y: f32) -> Agent {
+        let anim = |name: &str, a: &Anim| Animation {
+            name: name.to_string(),
+            row: a.row,
+            frames: a.frames,
+            fps: a.fps,
+        };
+        Agent {
+            species,
+            x,
+            y,
+            vy: 0.0,
+            hunger: 0,
+            dir: Dir::East,
+            job: Job::Walk,
+            sprite: AnimatedSprite::new(
+                kind.frame[0],
+                kind.frame[1],
+                &[anim("idle", &kind.idle), anim("walk", &kind.walk)],
+                true,
+            )
+        }
+    }
+
+    /// Moves and works for a tick. The sprite is animated separately, as
+    /// that goes by the frame.
+    pub fn step(&mut self, species: &Species, ground: &Ground, w:usize, h:usize) -> Vec<GroundChange> {
+
+        let mut v:Vec<GroundChange> = Vec::new();
+        let odds = &species.odds;
+
+        if one_in(odds.turn) {
+            self.dir = self.dir.op();
+        }
+
+        // Wind down at dusk, sleep through the night and get up in the morning
+        let night = ground.daylight < DUSK;
+
+        match self.job {
+            Job::Idle if night => {
+                if one_in(odds.dusk) {
+                    self.job = Job::Sleep;
+                    self.sprite.set_animation(0);
+                }
+            },
+            Job::Walk | Job::Build | Job::Bridge | Job::Dig if night => {
+                if one_in(odds.dusk) {
+                    self.job = Job::Idle;
+                    self.sprite.set_animation(0);
+                }
+            },
+            Job::Sleep => {
+                if !night && one_in(odds.dusk) {
+                    self.job = Job::Idle;
+                }
+            },
+            Job::Idle => {
+                if one_in(odds.start) {
+                    if one_in(odds.dig) {
+                        self.job = Job::Dig;
+                        self.sprite.set_animation(0);
+                    } else {
+                        self.job = Job::Walk;
+                        self.sprite.set_animation(1);
+
+                    }
+                } else if self.hunger > STARVING {
+                    self.job = Job::Walk;
+                    self.sprite.set_animation(1);
+                }
+            },
+            Job::Walk => {
+                if one_in(odds.stop) {
+                    if one_in(odds.rest) {
+                        self.job = Job::Idle;
+                        self.sprite.set_animation(0);
+                    } else {
+                        if one_in(odds.build) {
+                            self.job = Job::Build;
+                        } else {
+                            self.job = Job::Bridge;
+                        }
+                        self.sprite.set_animation(1);
+                    }
+                }
+            },
+            Job::Build | Job::Bridge => {
+                if one_in(odds.stop) {
+                    self.job = Job::Idle;
+                    self.sprite.set_animation(0);
+                }
+            },
+            Job::Dig => {
+                if one_in(odds.dug) {
+                    self.job = Job::Idle;
+                    self.sprite.set_animation(0);
+                    v.extend(self.footprint(species, &species.footprints.plant, CellType::Seed, false));
+                }
+            }
+        }
+
+        // Too hungry to work, it goes looking for food
+        let starving = self.hunger > STARVING;
+        if starving && matches!(self.job, Job::Dig | Job::Build | Job::Bridge) {
+            self.job = Job::Walk;
+            self.sprite.set_animation(1);
+        }
+
+        let (cx, cy) = species.centre();
+        let [fx, fy] = species.foot;
+        let speed = if starving { species.speed * 0.5 } else { species.speed };
+        let sp: f32 = if self.dir == Dir::West { -speed } else { speed };
+        // Walking with the wind is quicker than walking into it
+        let wind = ground.wind_at(self.x as i32 + cx as i32, self.y as i32 + cy as i32).x;
+        let mut xo = sp * (1.0 + wind * sp.signum() * 0.5);
+        let is_idle = self.job == Job::Idle;
+        if is_idle || self.job == Job::Dig || self.job == Job::Sleep {
+            xo = 0.0;
+        }
+        let g = ground.get_cell(self.x as i32 + fx, self.y as i32 + fy);
+        let g2 = ground.get_cell(self.x as i32 + fx, self.y as i32 + fy + 1);
+        // Climb
+        if is_solid(g) && is_solid(g2) {
+            self.y -= 1.0;
+            if g == CellType::Tree {
+                xo = 0.0;
+            }
+        }
+        // Fall
+        if !is_solid(g) && !is_solid(g2) {
+            self.vy += 1.0;
+            self.y += self.vy;
+            let g3 = ground.get_cell(self.x as i32 + fx, self.y as i32 + fy + 2);
+            if !is_solid(g3) {
+                if species.drifts {
+                    self.x -= sp;
+                } else {
+                    xo = 0.0;
+                }
+                self.y += 1.0;
+            }
+        } else {
+            self.vy = 0.0;
+        }
+
+        self.x += xo;
+
+        // Graze on whatever's growing underfoot
+        self.hunger += 1;
+        if self.hunger > HUNGRY {
+            let food = ground.get_cell(self.x as i32 + fx, self.y as i32 + fy + 1);
+            if food == CellType::Grass || food == CellType::Moss {
+                let left = if food == CellType::Grass { CellType::Soil } else { CellType::Wood };
+                v.push((self.x as i32 + fx, self.y as i32 + fy + 1, left));
+                self.hunger = 0;
+            }
+        }
+
+        // Jobs
+        let prints = &species.footprints;
+        match self.job {
+            Job::Dig => {
+                self.vy = 0.0;
+                v.extend(self.footprint(species, &prints.dig, CellType::Empty, false));
+            },
+            Job::Build => v.extend(self.footprint(species, &prints.build, CellType::Wood, true)),
+            Job::Bridge => v.extend(self.footprint(species, &prints.bridge, CellType::Wood, true)),
+            _ => {},
+        }
+
+        // Wrap round the edges the ground does, turn back at the others
+        match ground.topology {
+            Topology::Walled => {
+                if self.x < -cx || self.x > (w as f32) - cx - 1.0 {
+                    self.x = self.x.clamp(-cx, (w as f32) - cx - 1.0);
+                    self.dir = self.dir.op();
+                }
+            },
+            Topology::WrapX | Topology::Wrap => {
+                if self.x + cx < 0.0 {
+                    self.x += w as f32;
+                } else if self.x + cx >= w as f32 {
+                    self.x -= w as f32;
+                }
+            },
+            Topology::Infinite => {},
+        }
+        let fh = species.frame[1] as f32;
+        if ground.topology == Topology::Wrap {
+            if self.y + cy < 0.0 {
+                self.y += h as f32;
+            } else if self.y + cy >= h as f32 {
+                self.y -= h as f32;
+            }
+        } else if self.y < -fh {
+            self.y = (h as f32) - fh;
+        }
+        return v;
+    }
+
+    /// World position of the middle of its frame
+    pub fn centre(&self, species: &Species) -> (f32, f32) {
+        let (cx, cy) = species.centre();
+        return (self.x + cx, self.y + cy);
+    }
+
+    /// Sets the cells of a footprint to `cell`, mirroring it facing west if
+    /// it's for facing east
+    fn footprint(&self, species: &Species, cells: &[[i32; 2]], cell: CellType, ahead: bool) -> Vec<GroundChange> {
+        let x = self.x as i32 + species.foot[0];
+        let y = self.y as i32 + species.foot[1];
+        let flip = ahead && self.dir == Dir::West;
+        return cells.iter()
+            .map(|&[dx, dy]| (if flip { x - dx - 1 } else { x + dx }, y + dy, cell))
+            .collect();
+    }
+}
+
+/// Scatters each species' agents over the top half of a world w by h,
+/// some facing west and some standing about
+pub fn spawn(species: &[Species], w: usize, h: usize) -> Vec<Agent> {
+    let mut agents = Vec::new();
+    for (s, sp) in species.iter().enumerate() {
+        for _ in 0..sp.count {
+            let mut a = Agent::new(
+                s,
+                sp,
+                rand::gen_range(0, w) as f32,
+                rand::gen_range(0, h / 2) as f32);
+            if one_in(2) {
+                a.dir = Dir::West;
+            }
+            if one_in(2) {
+                a.job = Job::Idle;
+            }
+            agents.push(a);
+        }
+    }
+    return agents;
+}
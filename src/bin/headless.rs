@@ -0,0 +1,119 @@
+#![allow(clippy::needless_return)]
+
+//! Runs the simulation without a window for a number of ticks, writing a
+//! screenshot of the end and/or a recording of the whole run, e.g.
+//!
+//!     headless --ticks 3000 --record timelapse.gif --frame_every 30
+
+use std::process::exit;
+use macroquad::prelude::*;
+use pixmoosh::{capture, ground, agent, resources, maf, config, weather, wind, clock, light, render, terrain};
+use ground::{Ground, Topology};
+use config::Config;
+use weather::Weather;
+use wind::Wind;
+use clock::{Clock, TICK_RATE};
+use light::LightMap;
+use render::Canvas;
+use capture::Recorder;
+
+fn main() {
+    let config = Config::load(macroquad::miniquad::date::now() as _);
+    if config.screenshot.is_none() && config.record.is_none() {
+        eprintln!("Nothing to do: give a --screenshot or --record file");
+        exit(1);
+    }
+    let w = config.width.unwrap_or(640);
+    let h = config.height.unwrap_or(360);
+    rand::srand(config.seed);
+    maf::reseed(config.seed);
+    let mut species = agent::load(&config.species).unwrap_or_else(|e| {
+        eprintln!("Couldn't load species, using the built-in ones: {}", e);
+        agent::builtin()
+    });
+    let sheets = resources::load_sheets(&species).unwrap_or_else(|e| {
+        eprintln!("Couldn't load sprites, using the built-in species: {}", e);
+        species = agent::builtin();
+        resources::load_sheets(&species).unwrap_or_else(|e| {
+            eprintln!("Couldn't load sprites: {}", e);
+            exit(1);
+        })
+    });
+
+    let mut ground = Ground::new(w, h);
+    terrain::generate(&mut ground, &config.terrain, config.seed);
+    ground.wind = Wind::new(w, h, config.seed);
+    // An endless world needs a camera to follow, so it stops at the walls here
+    ground.topology = if config.topology == Topology::Infinite { Topology::Walled } else { config.topology };
+
+    let mut weather = Weather::new(config.seed);
+    weather.closed = config.closed_water;
+    weather.water = config.sky_water;
+    if let Some(precip) = config.weather {
+        weather.set(precip, w);
+    }
+    let mut clock = Clock::new(config.day_length);
+
+    let mut agents = agent::spawn(&species, w, h);
+
+    let mut lights = LightMap::new(&ground);
+    let mut canvas = Canvas::new(w, h);
+    canvas.patterns = config.patterns;
+    let every = config.frame_every as u64;
+    let mut recorder = config.record.as_ref().map(|path| {
+        Recorder::start(path, w as u16, h as u16, every as f32 / TICK_RATE).unwrap_or_else(|e| {
+            eprintln!("Couldn't record to {}: {}", path, e);
+            exit(1);
+        })
+    });
+
+    for t in 0..=config.ticks {
+        let last = t == config.ticks;
+        if (recorder.is_some() && t % every == 0) || last {
+            lights.update(&ground);
+            let tint = clock.tint();
+            canvas.paint(&ground, &lights, tint, ground.origin);
+            let frame = capture::compose(&canvas, &ground, &lights, tint, &agents, &species, &sheets);
+            if let Some(r) = &mut recorder {
+                if let Err(e) = r.add(&frame) {
+                    eprintln!("Couldn't record to {}: {}", r.path, e);
+                    exit(1);
+                }
+            }
+            if let (true, Some(path)) = (last, &config.screenshot) {
+                if let Err(e) = capture::save_png(path, &frame) {
+                    eprintln!("Couldn't save {}: {}", path, e);
+                    exit(1);
+                }
+            }
+        }
+        if last {
+            break;
+        }
+
+        clock.advance();
+        ground.daylight = clock.daylight();
+        weather.update(&mut ground);
+        ground.update();
+        for a in agents.iter_mut() {
+            for gc in a.step(&species[a.species], &ground, w, h) {
+                ground.set_cell(gc.0, gc.1, gc.2);
+            }
+        }
+        // Sprites play at about ten frames a second
+        if t % 6 == 5 {
+            for a in agents.iter_mut() {
+                capture::advance(&mut a.sprite);
+            }
+        }
+    }
+
+    if let Some(r) = recorder {
+        let (path, frames) = (r.path.clone(), r.frames);
+        if let Err(e) = r.finish() {
+            eprintln!("Couldn't record to {}: {}", path, e);
+            exit(1);
+        }
+        println!("Recorded {} frames to {}", frames, path);
+    }
+}
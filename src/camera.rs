@@ -0,0 +1,90 @@
+use macroquad::prelude::*;
+
+/// Largest number of screen pixels a cell can be drawn across
+pub const MAX_ZOOM: u32 = 8;
+
+/// Which part of the world is on screen, and how big its cells are drawn
+pub struct Camera {
+    /// World position of the top left corner of the screen
+    pub x: f32,
+    pub y: f32,
+    /// Screen pixels to a cell
+    pub zoom: u32,
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        return Camera::new();
+    }
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {
+            x: 0.0,
+            y: 0.0,
+            zoom: 1,
+        }
+    }
+
+    /// Cells across and down a screen sw by sh pixels shows
+    pub fn view(&self, sw: f32, sh: f32) -> (f32, f32) {
+        return (sw / self.zoom as f32, sh / self.zoom as f32);
+    }
+
+    /// World position under a point on the screen
+    pub fn to_world(&self, sx: f32, sy: f32) -> (f32, f32) {
+        return (self.x + sx / self.zoom as f32, self.y + sy / self.zoom as f32);
+    }
+
+    /// Screen position of a point in the world
+    pub fn to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        return ((x - self.x) * self.zoom as f32, (y - self.y) * self.zoom as f32);
+    }
+
+    /// Moves by sx, sy screen pixels
+    pub fn pan(&mut self, sx: f32, sy: f32) {
+        self.x += sx / self.zoom as f32;
+        self.y += sy / self.zoom as f32;
+    }
+
+    /// Zooms in (or out, if negative) by `steps`, keeping whatever's under
+    /// the screen point sx, sy where it is
+    pub fn zoom_by(&mut self, steps: i32, sx: f32, sy: f32) {
+        let (wx, wy) = self.to_world(sx, sy);
+        self.zoom = (self.zoom as i32 + steps).clamp(1, MAX_ZOOM as i32) as u32;
+        self.x = wx - sx / self.zoom as f32;
+        self.y = wy - sy / self.zoom as f32;
+    }
+
+    /// Keeps the view on a world w by h cells from x0 across, centring it
+    /// if it's smaller than the screen. With `endless` set it can go any
+    /// distance across.
+    pub fn clamp(&mut self, x0: f32, w: f32, h: f32, sw: f32, sh: f32, endless: bool) {
+        let (vw, vh) = self.view(sw, sh);
+        if !endless {
+            self.x = if vw >= w { x0 + (w - vw) / 2.0 } else { self.x.clamp(x0, x0 + w - vw) };
+        }
+        self.y = if vh >= h { (h - vh) / 2.0 } else { self.y.clamp(0.0, h - vh) };
+    }
+
+    /// Zooms in as far as a world w by h cells from x0 across still fits on
+    /// the screen, and centres it
+    pub fn fit(&mut self, x0: f32, w: f32, h: f32, sw: f32, sh: f32) {
+        let z = (sw / w).min(sh / h).floor() as u32;
+        self.zoom = z.clamp(1, MAX_ZOOM);
+        let (vw, vh) = self.view(sw, sh);
+        self.x = x0 + (w - vw) / 2.0;
+        self.y = (h - vh) / 2.0;
+    }
+
+    /// A camera for drawing in world coordinates, snapped so cells line up
+    /// with screen pixels
+    pub fn camera2d(&self, sw: f32, sh: f32) -> Camera2D {
+        let z = self.zoom as f32;
+        let (vw, vh) = self.view(sw, sh);
+        let x = (self.x * z).round() / z;
+        let y = (self.y * z).round() / z;
+        return Camera2D::from_display_rect(Rect::new(x, y, vw, vh));
+    }
+}
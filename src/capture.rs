@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use macroquad::prelude::*;
+use macroquad::experimental::animation::AnimatedSprite;
+use crate::ground::Ground;
+use crate::light::LightMap;
+use crate::render::{BG, Canvas};
+use crate::agent::{Agent, Dir, Species};
+
+/// How hard the GIF encoder works at picking each frame's colours, 1 (best)
+/// to 30 (fastest)
+const GIF_SPEED: i32 = 10;
+
+/// Lays `src` over the opaque pixel at `dst`
+fn blend(dst: &mut [u8], src: Color) {
+    for (k, s) in [src.r, src.g, src.b].into_iter().enumerate() {
+        let d = dst[k] as f32 / 255.0;
+        dst[k] = ((s * src.a + d * (1.0 - src.a)) * 255.0) as u8;
+    }
+}
+
+/// Draws part of a sprite sheet onto a frame with its top left at x, y,
+/// multiplied by `light`. Whatever of the part is off the sheet is left out.
+pub fn stamp(frame: &mut Image, sheet: &Image, source: Rect, x: i32, y: i32, flip: bool, light: Color) {
+    let (w, h) = (frame.width as i32, frame.height as i32);
+    for sy in 0..source.h as i32 {
+        for sx in 0..source.w as i32 {
+            let (fx, fy) = (x + sx, y + sy);
+            if fx < 0 || fy < 0 || fx >= w || fy >= h {
+                continue;
+            }
+            let col = if flip { source.w as i32 - 1 - sx } else { sx };
+            let (px, py) = (source.x as i32 + col, source.y as i32 + sy);
+            if px < 0 || py < 0 || px >= sheet.width as i32 || py >= sheet.height as i32 {
+                continue;
+            }
+            let c = sheet.get_pixel(px as u32, py as u32);
+            if c.a == 0.0 {
+                continue;
+            }
+            let i = (fy * w + fx) as usize * 4;
+            blend(&mut frame.bytes[i..i + 4], Color::new(c.r * light.r, c.g * light.g, c.b * light.b, c.a));
+        }
+    }
+}
+
+/// Puts together what's on screen without the GPU: the painted canvas over
+/// the background, with the agents on top. The canvas has to have been
+/// painted from the left edge of the ground.
+pub fn compose(canvas: &Canvas, ground: &Ground, lights: &LightMap, tint: Color,
+               agents: &[Agent], species: &[Species], sheets: &[Image]) -> Image {
+    let bg = Color::new(BG.r * tint.r, BG.g * tint.g, BG.b * tint.b, 1.0);
+    let mut frame = Image::gen_image_color(canvas.image.width, canvas.image.height, bg);
+    for (i, px) in canvas.image.bytes.chunks(4).enumerate() {
+        let c = Color::from_rgba(px[0], px[1], px[2], px[3]);
+        blend(&mut frame.bytes[i * 4..i * 4 + 4], c);
+    }
+
+    for a in agents {
+        let (cx, cy) = a.centre(&species[a.species]);
+        let light = lights.light_at(cx as i32 - ground.origin, cy as i32, tint);
+        let x = a.x as i32 - ground.origin;
+        stamp(&mut frame, &sheets[a.species], a.sprite.frame().source_rect, x, a.y as i32, a.dir == Dir::West, light);
+    }
+    return frame;
+}
+
+pub fn save_png(path: &str, image: &Image) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.bytes)?;
+    return Ok(());
+}
+
+/// Writes frames to an animated GIF, or an animated PNG if the path ends in
+/// .png or .apng, as they come in
+pub struct Recorder {
+    pub path: String,
+    pub frames: usize,
+    width: u16,
+    height: u16,
+    /// Hundredths of a second each frame is shown for
+    delay: u16,
+    gif: Option<gif::Encoder<BufWriter<File>>>,
+    /// An animated PNG needs to know how many frames it has before the
+    /// first is written, so they're held until the end
+    held: Vec<Vec<u8>>,
+}
+
+impl Recorder {
+    /// Starts a recording of frames `seconds` apart
+    pub fn start(path: &str, width: u16, height: u16, seconds: f32) -> io::Result<Recorder> {
+        let gif = if path.ends_with(".gif") {
+            let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), width, height, &[])
+                .map_err(Error::other)?;
+            encoder.set_repeat(gif::Repeat::Infinite).map_err(Error::other)?;
+            Some(encoder)
+        } else if path.ends_with(".png") || path.ends_with(".apng") {
+            None
+        } else {
+            return Err(Error::new(ErrorKind::InvalidInput, "recordings have to be .gif, .png or .apng"));
+        };
+        return Ok(Recorder {
+            path: path.to_string(),
+            frames: 0,
+            width,
+            height,
+            delay: ((seconds * 100.0).round() as u16).max(1),
+            gif,
+            held: Vec::new(),
+        });
+    }
+
+    pub fn add(&mut self, image: &Image) -> io::Result<()> {
+        if image.width != self.width || image.height != self.height {
+            return Err(Error::new(ErrorKind::InvalidInput, "frame is the wrong size"));
+        }
+        self.frames += 1;
+        match &mut self.gif {
+            Some(encoder) => {
+                let mut bytes = image.bytes.clone();
+                let mut frame = gif::Frame::from_rgba_speed(self.width, self.height, &mut bytes, GIF_SPEED);
+                frame.delay = self.delay;
+                encoder.write_frame(&frame).map_err(Error::other)?;
+            }
+            None => self.held.push(image.bytes.clone()),
+        }
+        return Ok(());
+    }
+
+    /// Writes out whatever's left and closes the file
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(encoder) = self.gif {
+            encoder.into_inner()?.flush()?;
+            return Ok(());
+        }
+        if self.held.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "nothing was recorded"));
+        }
+        let file = BufWriter::new(File::create(&self.path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.held.len() as u32, 0)?;
+        encoder.set_frame_delay(self.delay, 100)?;
+        let mut writer = encoder.write_header()?;
+        for bytes in self.held.iter() {
+            writer.write_image_data(bytes)?;
+        }
+        writer.finish()?;
+        return Ok(());
+    }
+}
+
+/// Moves a sprite on to its next frame. Sprites animate by themselves with
+/// the frame time, which there isn't without a window.
+pub fn advance(sprite: &mut AnimatedSprite) {
+    if !sprite.playing {
+        return;
+    }
+    let f = sprite.frame().source_rect;
+    sprite.set_frame((f.x / f.w) as u32 + 1);
+    sprite.set_animation(sprite.current_animation());
+}
@@ -0,0 +1,192 @@
+use crate::ground::{Ground, CellType, CHUNK, is_liquid};
+use crate::maf::{hash, hash_f32, noise};
+use crate::terrain::{Preset, column};
+
+/// Rounds of smoothing. Each round looks one cell further out, so this is
+/// also how far past its edges a strip has to be worked out.
+const SMOOTHING: i32 = 4;
+
+/// Rows of stone left between the topsoil and the caves below it
+const ROOF: i32 = 3;
+
+/// Caves are worked out a strip of this many columns at a time
+pub const STRIP: i32 = CHUNK as i32 * 4;
+
+/// Caves smaller than this aren't worth a shaft and are filled in
+const SMALLEST: usize = 80;
+
+/// Carves caves into the stone of columns x0 to x1, a strip at a time.
+/// Noise is smoothed into caves by a cellular automaton, then every cave is
+/// joined to the open air by a shaft, or filled in if it's tiny or the only
+/// way up is through water.
+pub fn carve(ground: &mut Ground, preset: &Preset, seed: u64, x0: i32, x1: i32) {
+    if preset.caves <= 0.0 {
+        return;
+    }
+    let mut sx = x0.div_euclid(STRIP) * STRIP;
+    while sx < x1 {
+        let a = sx.max(x0);
+        let b = (sx + STRIP).min(x1);
+        let open = smooth(preset, seed, a, b, ground.h);
+        let w = (b - a) as usize;
+        for y in 0..ground.h as i32 {
+            for x in a..b {
+                if open[y as usize * w + (x - a) as usize] && ground.get_cell(x, y) == CellType::Stone {
+                    ground.set_cell(x, y, CellType::Empty);
+                }
+            }
+        }
+        connect(ground, seed, a, b);
+        sx += STRIP;
+    }
+}
+
+/// Which cells of columns x0 to x1 are open cave after smoothing
+fn smooth(preset: &Preset, seed: u64, x0: i32, x1: i32, h: usize) -> Vec<bool> {
+    let rx = x0 - SMOOTHING;
+    let rw = (x1 - x0 + SMOOTHING * 2) as usize;
+    let mut allowed = vec![false; rw * h];
+    let mut open = vec![false; rw * h];
+    for i in 0..rw {
+        let x = rx + i as i32;
+        let c = column(preset, seed, x, h);
+        for y in (c.surface + c.depth + ROOF).max(0) as usize..h.saturating_sub(1) {
+            allowed[y * rw + i] = true;
+            // Broad noise makes some stretches of rock riddled and some solid
+            let p = preset.caves + noise(seed.wrapping_add(10), x as f32 / 40.0, y as f32 / 25.0) - 0.5;
+            open[y * rw + i] = hash_f32(seed.wrapping_add(8), x, y as i32) < p;
+        }
+    }
+
+    // A cell is open if most of the 3x3 around it is. Cells past the edge
+    // count as stone, which is only wrong in the margin we throw away.
+    for _ in 0..SMOOTHING {
+        let mut next = vec![false; rw * h];
+        for y in 0..h {
+            for x in 0..rw {
+                let i = y * rw + x;
+                if !allowed[i] {
+                    continue;
+                }
+                let mut n = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(h) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(rw) {
+                        if open[ny * rw + nx] {
+                            n += 1;
+                        }
+                    }
+                }
+                next[i] = n >= 5;
+            }
+        }
+        open = next;
+    }
+
+    let w = (x1 - x0) as usize;
+    let mut out = vec![false; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            out[y * w + x] = open[y * rw + x + SMOOTHING as usize];
+        }
+    }
+    return out;
+}
+
+/// Empty cells of columns x0 to x1 joined to (x, y), marking them reached
+fn flood(ground: &Ground, reached: &mut [bool], x0: i32, x1: i32, x: i32, y: i32) -> Vec<(i32, i32)> {
+    let w = (x1 - x0) as usize;
+    let mut found = Vec::new();
+    let mut todo = vec![(x, y)];
+    while let Some((x, y)) = todo.pop() {
+        if x < x0 || x >= x1 || y < 0 || y >= ground.h as i32 {
+            continue;
+        }
+        let i = y as usize * w + (x - x0) as usize;
+        if reached[i] || ground.get_cell(x, y) != CellType::Empty {
+            continue;
+        }
+        reached[i] = true;
+        found.push((x, y));
+        todo.extend_from_slice(&[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+    }
+    return found;
+}
+
+/// Joins every cave in columns x0 to x1 to the open air above them
+fn connect(ground: &mut Ground, seed: u64, x0: i32, x1: i32) {
+    let w = (x1 - x0) as usize;
+    let mut reached = vec![false; w * ground.h];
+    for x in x0..x1 {
+        flood(ground, &mut reached, x0, x1, x, 0);
+    }
+
+    // Going top down, the first cell found of each cave is its highest
+    for y in 0..ground.h as i32 {
+        for x in x0..x1 {
+            let i = y as usize * w + (x - x0) as usize;
+            if reached[i] || ground.get_cell(x, y) != CellType::Empty {
+                continue;
+            }
+            let cave = flood(ground, &mut reached, x0, x1, x, y);
+            let path = if cave.len() < SMALLEST { None } else { shaft(ground, &reached, seed, x0, x1, x, y) };
+            match path {
+                Some(path) => {
+                    for &(px, py) in path.iter() {
+                        ground.set_cell(px, py, CellType::Empty);
+                    }
+                    for &(px, py) in path.iter() {
+                        flood(ground, &mut reached, x0, x1, px, py);
+                    }
+                }
+                None => {
+                    for (px, py) in cave {
+                        ground.set_cell(px, py, CellType::Stone);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Plots a two cell wide shaft wandering up from (x, y) until it meets the
+/// open air, lining it with stone where the ground would fall in. None if
+/// it would let water in.
+fn shaft(ground: &mut Ground, reached: &[bool], seed: u64, x0: i32, x1: i32, x: i32, y: i32) -> Option<Vec<(i32, i32)>> {
+    let w = (x1 - x0) as usize;
+    if w < 2 {
+        return None;
+    }
+    let mut path = Vec::new();
+    let mut lining = Vec::new();
+    let mut tx = x.clamp(x0, x1 - 2);
+    for ty in (0..y).rev() {
+        // Start straight up so the shaft touches the cave
+        if ty < y - 1 {
+            tx = (tx + (hash(seed.wrapping_add(9), tx, ty) % 3) as i32 - 1).clamp(x0, x1 - 2);
+        }
+        let mut open_air = false;
+        for cx in tx..tx + 2 {
+            let c = ground.get_cell(cx, ty);
+            if is_liquid(c) {
+                return None;
+            }
+            if c == CellType::Empty && reached[ty as usize * w + (cx - x0) as usize] {
+                open_air = true;
+            } else {
+                path.push((cx, ty));
+            }
+        }
+        if open_air {
+            for (lx, ly) in lining {
+                ground.set_cell(lx, ly, CellType::Stone);
+            }
+            return Some(path);
+        }
+        for lx in [tx - 1, tx + 2] {
+            if lx >= x0 && lx < x1 && matches!(ground.get_cell(lx, ty), CellType::Sand | CellType::Soil) {
+                lining.push((lx, ty));
+            }
+        }
+    }
+    return None;
+}
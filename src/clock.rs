@@ -0,0 +1,134 @@
+use macroquad::prelude::*;
+use std::f32::consts::TAU;
+
+/// Ticks in a whole day and night
+pub const DAY_LENGTH: u64 = 12000;
+
+/// Daylight below which it counts as night
+pub const DUSK: f32 = 0.25;
+
+/// Ticks a second at normal speed
+pub const TICK_RATE: f32 = 60.0;
+
+/// Speeds the simulation can be run at
+pub const SPEEDS: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+/// Most ticks run in one frame, so a slow frame can't snowball into more
+/// slow frames
+const MAX_TICKS: u32 = 64;
+
+pub struct Clock {
+    pub tick: u64,
+    pub day_length: u64,
+}
+
+impl Clock {
+    /// Starts a new world in the morning
+    pub fn new(day_length: u64) -> Clock {
+        Clock {
+            tick: day_length / 3,
+            day_length,
+        }
+    }
+
+    pub fn advance(&mut self) {
+        self.tick += 1;
+    }
+
+    pub fn day(&self) -> u64 {
+        return self.tick / self.day_length;
+    }
+
+    /// Hours and minutes on a 24 hour clock
+    pub fn hh_mm(&self) -> (u32, u32) {
+        let minutes = (self.time_of_day() * 24.0 * 60.0) as u32;
+        return (minutes / 60, minutes % 60);
+    }
+
+    /// 0 at midnight, 0.5 at noon
+    pub fn time_of_day(&self) -> f32 {
+        return (self.tick % self.day_length) as f32 / self.day_length as f32;
+    }
+
+    /// How bright the sun is, 0 at midnight to 1 at noon
+    pub fn daylight(&self) -> f32 {
+        let sun = 0.5 - 0.5 * (self.time_of_day() * TAU).cos();
+        // Stay fully light (or dark) for a while around noon (and midnight)
+        return ((sun - 0.5) * 1.6 + 0.5).clamp(0.0, 1.0);
+    }
+
+    /// Colour to shade the world with: dark blue at night, warm at dawn and dusk
+    pub fn tint(&self) -> Color {
+        let d = self.daylight();
+        let dusk = 1.0 - (d - 0.5).abs() * 2.0;
+        return Color::new(
+            0.25 + 0.75 * d,
+            0.3 + 0.7 * d - 0.1 * dusk,
+            0.5 + 0.5 * d - 0.2 * dusk,
+            1.0);
+    }
+}
+
+/// Decides how many ticks to run each frame, so the simulation runs at a
+/// steady rate whatever the frame rate
+pub struct Pacer {
+    /// Index into SPEEDS
+    pub speed: usize,
+    pub paused: bool,
+    /// Ticks owed but not yet run, as a fraction of a tick
+    owed: f32,
+    /// A single tick asked for while paused
+    step: bool,
+}
+
+impl Default for Pacer {
+    fn default() -> Pacer {
+        return Pacer::new();
+    }
+}
+
+impl Pacer {
+    pub fn new() -> Pacer {
+        Pacer {
+            speed: 2,
+            paused: false,
+            owed: 0.0,
+            step: false,
+        }
+    }
+
+    /// How many times normal speed it's running at
+    pub fn speed(&self) -> f32 {
+        return SPEEDS[self.speed];
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    /// Runs a single tick next frame, pausing if it isn't already
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.step = true;
+    }
+
+    /// Ticks to run for a frame that took `dt` seconds
+    pub fn ticks(&mut self, dt: f32) -> u32 {
+        if self.paused {
+            self.owed = 0.0;
+            return std::mem::take(&mut self.step) as u32;
+        }
+        self.owed += dt * TICK_RATE * self.speed();
+        let n = self.owed as u32;
+        if n > MAX_TICKS {
+            self.owed = 0.0;
+            return MAX_TICKS;
+        }
+        self.owed -= n as f32;
+        return n;
+    }
+}
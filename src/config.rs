@@ -0,0 +1,162 @@
+use std::fs;
+use crate::weather::Precip;
+use crate::clock::DAY_LENGTH;
+use crate::terrain;
+use crate::ground::Topology;
+use crate::agent::SPECIES_DIR;
+
+/// Settings file read from the working directory, one `key = value` per line
+pub const CONFIG_FILE: &str = "pixmoosh.cfg";
+
+pub struct Config {
+    /// Seed for the world; the current time if not given
+    pub seed: u64,
+    /// Fixed weather, or None to let it change by itself
+    pub weather: Option<Precip>,
+    /// Rain has to come from evaporated water, rather than out of nowhere
+    pub closed_water: bool,
+    /// Cells' worth of water the clouds start out holding
+    pub sky_water: u32,
+    /// Ticks in a day and night
+    pub day_length: u64,
+    /// Name of the terrain preset to generate the world with
+    pub terrain: String,
+    /// Which edges of the world wrap round
+    pub topology: Topology,
+    /// Size of the world in cells, or of the view an endless world's loaded
+    /// around; the window's size at startup if not given
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    /// Draw planks and bark on wood and trees
+    pub patterns: bool,
+    /// PNG to write the last frame to, when running headless
+    pub screenshot: Option<String>,
+    /// GIF or animated PNG to record to from the start
+    pub record: Option<String>,
+    /// Ticks between recorded frames
+    pub frame_every: u32,
+    /// Ticks to run for when headless
+    pub ticks: u64,
+    /// Directory of species files
+    pub species: String,
+}
+
+impl Config {
+    pub fn new(seed: u64) -> Config {
+        Config {
+            seed,
+            weather: None,
+            closed_water: true,
+            sky_water: 2000,
+            day_length: DAY_LENGTH,
+            terrain: terrain::CLASSIC.to_string(),
+            topology: Topology::Walled,
+            width: None,
+            height: None,
+            patterns: true,
+            screenshot: None,
+            record: None,
+            frame_every: 6,
+            ticks: 600,
+            species: SPECIES_DIR.to_string(),
+        }
+    }
+
+    /// Reads the settings file (if there is one), then the command line
+    /// (`--key=value` or `--key value`), later values winning.
+    pub fn load(seed: u64) -> Config {
+        let mut config = Config::new(seed);
+        if let Ok(text) = fs::read_to_string(CONFIG_FILE) {
+            for line in text.lines() {
+                let line = line.split('#').next().unwrap_or("").trim();
+                if let Some((key, value)) = line.split_once('=') {
+                    config.set(key.trim(), value.trim());
+                }
+            }
+        }
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let Some(arg) = arg.strip_prefix("--") else {
+                eprintln!("Ignoring argument {}", arg);
+                continue;
+            };
+            match arg.split_once('=') {
+                Some((key, value)) => config.set(key, value),
+                None => {
+                    let value = args.next().unwrap_or_default();
+                    config.set(arg, &value);
+                }
+            }
+        }
+        return config;
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "seed" => match value.parse() {
+                Ok(seed) => self.seed = seed,
+                Err(_) => eprintln!("Bad seed: {}", value),
+            },
+            "weather" => match value {
+                "auto" => self.weather = None,
+                "clear" => self.weather = Some(Precip::Clear),
+                "rain" => self.weather = Some(Precip::Rain),
+                "snow" => self.weather = Some(Precip::Snow),
+                _ => eprintln!("Unknown weather: {}", value),
+            },
+            "water_cycle" => match value {
+                "open" => self.closed_water = false,
+                "closed" => self.closed_water = true,
+                _ => eprintln!("Unknown water cycle: {}", value),
+            },
+            "sky_water" => match value.parse() {
+                Ok(water) => self.sky_water = water,
+                Err(_) => eprintln!("Bad sky water: {}", value),
+            },
+            "day_length" => match value.parse() {
+                Ok(ticks) if ticks > 0 => self.day_length = ticks,
+                _ => eprintln!("Bad day length: {}", value),
+            },
+            "terrain" => {
+                if value == terrain::CLASSIC || terrain::find(value).is_some() {
+                    self.terrain = value.to_string();
+                } else {
+                    eprintln!("Unknown terrain: {}", value);
+                }
+            },
+            "topology" => match value {
+                "walled" => self.topology = Topology::Walled,
+                "wrap_x" => self.topology = Topology::WrapX,
+                "wrap" => self.topology = Topology::Wrap,
+                "infinite" => self.topology = Topology::Infinite,
+                _ => eprintln!("Unknown topology: {}", value),
+            },
+            "width" => match value.parse() {
+                Ok(w) if w > 0 => self.width = Some(w),
+                _ => eprintln!("Bad width: {}", value),
+            },
+            "height" => match value.parse() {
+                Ok(h) if h > 0 => self.height = Some(h),
+                _ => eprintln!("Bad height: {}", value),
+            },
+            "patterns" => match value {
+                "on" => self.patterns = true,
+                "off" => self.patterns = false,
+                _ => eprintln!("Patterns are on or off, not {}", value),
+            },
+            "screenshot" => self.screenshot = Some(value.to_string()),
+            "record" => self.record = Some(value.to_string()),
+            "frame_every" => match value.parse() {
+                Ok(n) if n > 0 => self.frame_every = n,
+                _ => eprintln!("Bad frame_every: {}", value),
+            },
+            "ticks" => match value.parse() {
+                Ok(n) => self.ticks = n,
+                Err(_) => eprintln!("Bad ticks: {}", value),
+            },
+            "species" => self.species = value.to_string(),
+            _ => eprintln!("Unknown setting: {}", key),
+        }
+    }
+}
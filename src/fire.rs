@@ -0,0 +1,61 @@
+use crate::ground::{Patch, CellType};
+use crate::maf::{one_in, rand_neighbour};
+
+/// Ticks a fire burns on without fuel before it starts to die out
+const BURN_TIME: u8 = 40;
+
+pub fn is_flammable(t: CellType) -> bool {
+    matches!(t,
+        CellType::Wood |
+        CellType::Tree |
+        CellType::Leaf |
+        CellType::Seed |
+        CellType::Grass |
+        CellType::Moss)
+}
+
+/// Fire spreads to whatever will burn around it and is put out by water.
+/// Once there's nothing left to burn it dies out; its cell data is how long
+/// it's been without fuel.
+pub fn update_fire(ground: &mut Patch, x: i32, y: i32) {
+    let mut fuel = false;
+    for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+        let n = ground.get_cell(nx, ny);
+        if n == CellType::Water {
+            ground.set_cell(nx, ny, CellType::Vapour);
+            ground.set_cell(x, y, CellType::Empty);
+            return;
+        }
+        if is_flammable(n) {
+            fuel = true;
+            if one_in(8) {
+                ground.set_cell(nx, ny, CellType::Fire);
+            }
+        }
+    }
+    if fuel {
+        return;
+    }
+
+    let age = ground.get_data(x, y);
+    if age > BURN_TIME && one_in(10) {
+        ground.set_cell(x, y, CellType::Empty);
+        return;
+    }
+    ground.set_cell_with(x, y, CellType::Fire, age.saturating_add(1));
+}
+
+/// Lava boils water and sets things alight. Returns true if it's too
+/// sluggish to flow this tick.
+pub fn update_lava(ground: &mut Patch, x: i32, y: i32) -> bool {
+    if one_in(10) {
+        let (nx, ny) = rand_neighbour(x, y);
+        let n = ground.get_cell(nx, ny);
+        if n == CellType::Water {
+            ground.set_cell(nx, ny, CellType::Vapour);
+        } else if is_flammable(n) {
+            ground.set_cell(nx, ny, CellType::Fire);
+        }
+    }
+    return !one_in(3);
+}
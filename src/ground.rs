@@ -1,43 +1,119 @@
 use macroquad::prelude::*;
 use std::fmt;
-use crate::maf::one_in;
+use std::time::Instant;
+use rayon::prelude::*;
+use crate::plant;
+use crate::fire;
+use crate::maf::{self, one_in, chance, Rng};
+use crate::wind::Wind;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
 pub enum CellType {
     AntiSand,
     Bedrock,
     Empty,
     Sand,
+    Soil,
     Water,
     Wood,
     Tree,
+    Leaf,
+    Seed,
+    Grass,
+    Moss,
+    Snow,
+    Vapour,
+    Fire,
+    Lava,
+    Stone,
+    Ore,
 }
+impl CellType {
+    /// Every cell type, in id order
+    pub const ALL: [CellType; 18] = [
+        CellType::AntiSand,
+        CellType::Bedrock,
+        CellType::Empty,
+        CellType::Sand,
+        CellType::Soil,
+        CellType::Water,
+        CellType::Wood,
+        CellType::Tree,
+        CellType::Leaf,
+        CellType::Seed,
+        CellType::Grass,
+        CellType::Moss,
+        CellType::Snow,
+        CellType::Vapour,
+        CellType::Fire,
+        CellType::Lava,
+        CellType::Stone,
+        CellType::Ore,
+    ];
+
+    pub fn id(self) -> u8 {
+        return self as u8;
+    }
+
+    pub fn from_id(id: u8) -> Option<CellType> {
+        return CellType::ALL.get(id as usize).copied();
+    }
+}
+
 impl fmt::Display for CellType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             CellType::Empty => write!(f, "_"),
             CellType::Sand => write!(f, "S"),
+            CellType::Soil => write!(f, "D"),
+            CellType::Water => write!(f, "W"),
+            CellType::Tree => write!(f, "T"),
+            CellType::Leaf => write!(f, "L"),
+            CellType::Seed => write!(f, "."),
+            CellType::Grass => write!(f, "G"),
+            CellType::Moss => write!(f, "M"),
+            CellType::Snow => write!(f, "*"),
+            CellType::Vapour => write!(f, "~"),
+            CellType::Fire => write!(f, "^"),
+            CellType::Lava => write!(f, "%"),
+            CellType::Stone => write!(f, "#"),
+            CellType::Ore => write!(f, "$"),
             _ => write!(f, "?")
         }
     }
 }
 
 pub fn is_static(t: CellType) -> bool {
-    match t {
-        CellType::Wood => true,
-        CellType::Tree => true,
-        _ => false
-    }
+    matches!(t, CellType::Wood | CellType::Tree)
 }
 
 pub fn is_solid(t: CellType) -> bool {
-    match t {
-        CellType::Wood => true,
-        CellType::Tree => true,
-        CellType::Sand => true,
-        CellType::Bedrock => true,
-        _ => false
-    }
+    matches!(t,
+        CellType::Wood |
+        CellType::Tree |
+        CellType::Sand |
+        CellType::Soil |
+        CellType::Grass |
+        CellType::Moss |
+        CellType::Snow |
+        CellType::Stone |
+        CellType::Ore |
+        CellType::Bedrock)
+}
+
+/// Cells that seeds can take root in
+pub fn is_soil(t: CellType) -> bool {
+    matches!(t, CellType::Soil | CellType::Sand | CellType::Grass)
+}
+
+pub fn is_liquid(t: CellType) -> bool {
+    matches!(t, CellType::Water | CellType::Lava)
+}
+
+/// Light enough to be pushed around by the wind
+pub fn is_light(t: CellType) -> bool {
+    matches!(t, CellType::Snow | CellType::Seed | CellType::Vapour)
 }
 
 pub fn is_free(t: CellType) -> bool {
@@ -46,16 +122,235 @@ pub fn is_free(t: CellType) -> bool {
 
 pub type GroundChange = (i32, i32, CellType);
 
-pub struct Cell {
-    kind: CellType
+/// Which edges of the world join up with the opposite edge
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Topology {
+    /// Bedrock all round
+    Walled,
+    /// Left and right edges join, bedrock above and below
+    WrapX,
+    /// Every edge joins its opposite, like a torus
+    Wrap,
+    /// Left and right go on forever, a window of it loaded at a time
+    Infinite,
+}
+
+/// Side of the square chunks the ground is split into for bookkeeping
+pub const CHUNK: usize = 64;
+
+/// Furthest from itself a cell's update may look or change anything.
+/// Chunks updated at the same time have a whole chunk between them, so
+/// what one changes is never what another is looking at.
+pub const REACH: usize = 8;
+const _: () = assert!(REACH * 2 < CHUNK);
+const _: () = assert!(std::mem::size_of::<CellType>() == 1);
+
+/// A word of the moved map. It's a bit per cell, a row of a chunk to a
+/// word, or built with the moved_bytes feature a byte per cell, for the
+/// storage bench to time updates on both.
+#[cfg(not(feature = "moved_bytes"))]
+type Moved = u64;
+#[cfg(feature = "moved_bytes")]
+type Moved = u8;
+/// Cells to a word of the moved map
+const MOVED_SPAN: usize = if cfg!(feature = "moved_bytes") { 1 } else { CHUNK };
+const _: () = assert!(CHUNK.is_multiple_of(MOVED_SPAN) && MOVED_SPAN <= Moved::BITS as usize);
+
+/// Marks window cell (x, y) of a w wide ground as set on the tick. The
+/// moved map is never cleared as a whole: each row of a chunk keeps the
+/// tick it was last marked on, and its words are cleared when it's first
+/// marked on a later one.
+///
+/// SAFETY: the pointers are to the ground's moved map and its ticks, and
+/// nothing else is at that row of the chunk
+#[cfg_attr(feature = "moved_bytes", allow(clippy::modulo_one))]
+unsafe fn mark_moved(moved: *mut Moved, ticks: *mut u32, w: usize, tick: u32, x: usize, y: usize) {
+    let stride = w.div_ceil(MOVED_SPAN);
+    let row = y * w.div_ceil(CHUNK) + x / CHUNK;
+    if *ticks.add(row) != tick {
+        *ticks.add(row) = tick;
+        let start = y * stride + x / CHUNK * (CHUNK / MOVED_SPAN);
+        let end = (start + CHUNK / MOVED_SPAN).min((y + 1) * stride);
+        std::ptr::write_bytes(moved.add(start), 0, end - start);
+    }
+    *moved.add(y * stride + x / MOVED_SPAN) |= 1 << (x % MOVED_SPAN);
+}
+
+/// True if window cell (x, y) was marked on the tick
+///
+/// SAFETY: as for mark_moved
+#[cfg_attr(feature = "moved_bytes", allow(clippy::modulo_one))]
+unsafe fn was_moved(moved: *const Moved, ticks: *const u32, w: usize, tick: u32, x: usize, y: usize) -> bool {
+    let row = y * w.div_ceil(CHUNK) + x / CHUNK;
+    return *ticks.add(row) == tick
+        && *moved.add(y * w.div_ceil(MOVED_SPAN) + x / MOVED_SPAN) & 1 << (x % MOVED_SPAN) != 0;
+}
+
+/// Cells of a chunk that need updating, in window coordinates, inclusive
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl Region {
+    pub const EMPTY: Region = Region { x0: i32::MAX, y0: i32::MAX, x1: i32::MIN, y1: i32::MIN };
+
+    pub fn is_empty(&self) -> bool {
+        return self.x0 > self.x1;
+    }
+
+    fn add(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        self.x0 = self.x0.min(x0);
+        self.y0 = self.y0.min(y0);
+        self.x1 = self.x1.max(x1);
+        self.y1 = self.y1.max(y1);
+    }
+}
+
+impl Default for Region {
+    fn default() -> Region {
+        return Region::EMPTY;
+    }
+}
+
+/// Vapour rising into the top rows condenses into the clouds
+pub const CLOUD_LINE: i32 = 8;
+
+/// Slow changes, water evaporating and grass taking hold, are only tried
+/// every SLOW ticks, with odds SLOW times better, so the cells waiting on
+/// them can sleep in between
+pub const SLOW: u64 = 40;
+
+/// What updating a chunk did to the bookkeeping, held back until every
+/// chunk of the pass is done, as the chunks round it are shared with the
+/// other threads
+#[derive(Default)]
+struct Spill {
+    chunk: usize,
+    /// Cells set in the chunk itself
+    sets: u32,
+    wakes: Vec<(usize, Region)>,
+    bumps: Vec<usize>,
+    /// Cells set in other chunks, for their moved map
+    moves: Vec<(usize, usize)>,
+    condensed: u32,
+    /// Cells of the chunk itself waiting on slow changes
+    slow: Region,
+    /// Nanoseconds the chunk took
+    cost: u32,
+}
+
+/// Which of three passes a chunk across (or down) is in. Neighbours never
+/// share one, even round a wrapped edge, which takes a third pass when
+/// there's an odd number of chunks or a narrow one at the end.
+fn colour(i: usize, n: usize, size: usize, wraps: bool) -> usize {
+    if wraps && n > 1 && (n % 2 == 1 || !size.is_multiple_of(CHUNK)) {
+        if i == n - 2 {
+            return 2;
+        }
+        if i == n - 1 {
+            return 1;
+        }
+    }
+    return i % 2;
+}
+
+/// Index of the cell at world (x, y) in a window w by h cells starting at
+/// world x `origin`, wrapping round whichever edges the topology joins up
+fn index_in(w: usize, h: usize, topology: Topology, origin: i32, x: i32, y: i32) -> Option<usize> {
+    let (sw, sh) = (w as i32, h as i32);
+    let x = match topology {
+        Topology::Walled | Topology::Infinite => x - origin,
+        Topology::WrapX | Topology::Wrap => x.rem_euclid(sw),
+    };
+    let y = if topology == Topology::Wrap { y.rem_euclid(sh) } else { y };
+    if x < 0 || x >= sw || y < 0 || y >= sh {
+        return None;
+    }
+    return Some(y as usize * w + x as usize);
+}
+
+/// Calls `mark` with the chunk and region of the cell at window coordinates
+/// (x, y) and each of the cells around it
+fn around(w: usize, h: usize, cw: usize, topology: Topology, x: i32, y: i32, mut mark: impl FnMut(usize, Region)) {
+    let (sw, sh) = (w as i32, h as i32);
+    let c = CHUNK as i32;
+    // Usually all nine are in the one chunk
+    if x % c != 0 && x % c != c - 1 && x < sw - 1 && y % c != 0 && y % c != c - 1 && y < sh - 1 {
+        let i = (y / c) as usize * cw + (x / c) as usize;
+        mark(i, Region { x0: x - 1, y0: y - 1, x1: x + 1, y1: y + 1 });
+        return;
+    }
+    for ny in y - 1..=y + 1 {
+        for nx in x - 1..=x + 1 {
+            let nx = if matches!(topology, Topology::WrapX | Topology::Wrap) { nx.rem_euclid(sw) } else { nx };
+            let ny = if topology == Topology::Wrap { ny.rem_euclid(sh) } else { ny };
+            if nx < 0 || nx >= sw || ny < 0 || ny >= sh {
+                continue;
+            }
+            let i = (ny / c) as usize * cw + (nx / c) as usize;
+            mark(i, Region { x0: nx, y0: ny, x1: nx, y1: ny });
+        }
+    }
 }
 
 pub struct Ground {
     pub w: usize,
     pub h: usize,
+    /// A byte per cell
     pub cells: Vec<CellType>,
-    pub buf: Vec<CellType>,
-    pub moved: Vec<bool>,
+    /// The cells set this tick, see Moved
+    moved: Vec<Moved>,
+    /// Per row of each chunk, the tick its part of the moved map is for
+    moved_on: Vec<u32>,
+    /// Per-cell state, meaning depends on the cell type (see plant.rs)
+    pub data: Vec<u8>,
+    /// Per-cell shade, picking the cell's colour from its material's palette.
+    /// Set when a cell comes into being and carried round as it moves.
+    pub shade: Vec<u8>,
+    /// Air temperature in degrees, set by the weather
+    pub temperature: f32,
+    /// Vapour cells that have condensed into the sky, for the weather to collect
+    pub condensed: u32,
+    /// Sunlight from the clock, 0 to 1, that plants need to grow
+    pub daylight: f32,
+    pub wind: Wind,
+    pub topology: Topology,
+    /// World x of the first column. The ground is a window onto a wider
+    /// world, and everything outside takes world coordinates.
+    pub origin: i32,
+    /// Columns of chunks that get updated; the rest are left as they are
+    pub active: Vec<bool>,
+    /// Chunks across and down
+    pub cw: usize,
+    pub ch: usize,
+    /// Per chunk, the cells to update next tick: anything that changed, the
+    /// cells around it, and anything that might change by itself
+    pub dirty: Vec<Region>,
+    /// Per chunk, the cells being updated this tick
+    awake: Vec<Region>,
+    /// Per chunk, the cells that are only waiting on slow changes, woken
+    /// every SLOW ticks
+    slow: Vec<Region>,
+    /// Whether it was above freezing last tick, when snow and water last
+    /// decided whether to stay awake
+    warm: bool,
+    /// Bumped every time a cell in the chunk is set, so others can tell
+    /// which chunks have changed since they last looked
+    pub versions: Vec<u32>,
+    /// Per chunk, how many nanoseconds its last update took, 0 if it slept
+    pub cost: Vec<u32>,
+    /// Seeds the random streams each chunk draws from as it's updated
+    pub seed: u64,
+    /// Updates so far, so every tick gets fresh streams
+    tick: u64,
+    /// Per column, the first row that isn't empty, as of the start of the tick
+    sky: Vec<i32>,
+    /// Per column of chunks, the sum of their versions when sky was worked out
+    sky_seen: Vec<u32>,
 }
 impl fmt::Display for Ground {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -65,7 +360,7 @@ impl fmt::Display for Ground {
             for i in 0..w {
                 write!(f, "{}", self.cells[j * self.w + i])?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         return Ok(());
     }
@@ -78,16 +373,37 @@ impl Ground {
             w,
             h,
             cells:  vec![CellType::Empty; w * h],
-            buf:  vec![CellType::Empty; w * h],
-            moved: vec![false; w * h]
+            moved: vec![0; w.div_ceil(MOVED_SPAN) * h],
+            moved_on: vec![0; w.div_ceil(CHUNK) * h],
+            data: vec![0; w * h],
+            shade: vec![0; w * h],
+            temperature: 15.0,
+            condensed: 0,
+            daylight: 1.0,
+            wind: Wind::new(w, h, 0),
+            topology: Topology::Walled,
+            origin: 0,
+            active: vec![true; w.div_ceil(CHUNK)],
+            cw: w.div_ceil(CHUNK),
+            ch: h.div_ceil(CHUNK),
+            versions: vec![0; w.div_ceil(CHUNK) * h.div_ceil(CHUNK)],
+            cost: vec![0; w.div_ceil(CHUNK) * h.div_ceil(CHUNK)],
+            dirty: vec![Region::EMPTY; w.div_ceil(CHUNK) * h.div_ceil(CHUNK)],
+            awake: vec![Region::EMPTY; w.div_ceil(CHUNK) * h.div_ceil(CHUNK)],
+            slow: vec![Region::EMPTY; w.div_ceil(CHUNK) * h.div_ceil(CHUNK)],
+            warm: true,
+            seed: 0,
+            tick: 0,
+            sky: vec![h as i32; w],
+            sky_seen: vec![0; w.div_ceil(CHUNK)],
         }
     }
 
-    fn rnd_line(&mut self) {
-        let x1 = rand::gen_range(0, self.w);
-        let w = rand::gen_range(20, 130);
-        let y1 = rand::gen_range(40, self.h / 2 +100);
-        let h = rand::gen_range(5,10);
+    fn rnd_line(&mut self, rng: &mut Rng) {
+        let x1 = rng.range(0, self.w as i32) as usize;
+        let w = rng.range(20, 130) as usize;
+        let y1 = rng.range(40, self.h as i32 / 2 + 100) as usize;
+        let h = rng.range(5, 10) as usize;
         for x in x1..x1+w {
             for y in y1..y1+h {
                 self.set_cell(x as i32, y as i32, CellType::Wood);
@@ -95,12 +411,19 @@ impl Ground {
         }
     }
 
-    pub fn init(&mut self) {
+    /// The original world, a wooden floor with planks scattered above it,
+    /// placed the same way every time for the same seed
+    pub fn init(&mut self, seed: u64) {
+        let mut rng = Rng::new(seed);
         for y in 0..self.h as i32{
             for x in 0..self.w as i32 {
                 if y as usize > self.h / 2 + self.h / 4 {
                     self.set_cell(x, y, CellType::Wood);
                 }
+                else if y as usize > (self.h / 2 + self.h / 4).saturating_sub(4) {
+                    // A layer of topsoil for grass to take hold in
+                    self.set_cell(x, y, CellType::Soil);
+                }
                 else  {
                     /*if rand::gen_range(0, 25) == 0 {
                         self.set_cell(x, y, CellType::Sand);
@@ -110,136 +433,644 @@ impl Ground {
         }
         let num_lines = 30;// rand::gen_range(20, 40);
         for _ in 0..num_lines {
-            self.rnd_line();
+            self.rnd_line(&mut rng);
+        }
+    }
+
+    /// Updates the awake parts of the active chunks, bottom row first. The
+    /// chunks are updated in passes of chunks that aren't next to each other,
+    /// spread over every core, and each draws from a random stream of its
+    /// own, so it comes out the same however many threads there are.
+    pub fn update(&mut self) {
+        // Snow and water asleep in the cold wake up when it warms up
+        if (self.temperature > 0.0) != self.warm {
+            self.warm = self.temperature > 0.0;
+            self.wake_all();
+        }
+        if (self.tick + 1).is_multiple_of(SLOW) {
+            for (d, s) in self.dirty.iter_mut().zip(self.slow.iter_mut()) {
+                d.add(s.x0, s.y0, s.x1, s.y1);
+                *s = Region::EMPTY;
+            }
+        }
+
+        std::mem::swap(&mut self.awake, &mut self.dirty);
+        self.dirty.fill(Region::EMPTY);
+        for c in 0..self.awake.len() {
+            if !self.active[c % self.cw] {
+                // Left for when the chunk's active again
+                self.dirty[c] = self.awake[c];
+                self.awake[c] = Region::EMPTY;
+            }
+        }
+
+        self.cost.fill(0);
+        self.look_up();
+        self.tick += 1;
+
+        for pass in 0..9 {
+            let chunks: Vec<usize> = (0..self.awake.len())
+                .filter(|&c| !self.awake[c].is_empty() && self.pass_of(c) == pass)
+                .collect();
+            if chunks.is_empty() {
+                continue;
+            }
+            let spills: Vec<Spill> = self.patches(&chunks).into_par_iter()
+                .map(Patch::update)
+                .collect();
+            for s in spills {
+                self.cost[s.chunk] = s.cost;
+                self.versions[s.chunk] = self.versions[s.chunk].wrapping_add(s.sets);
+                for (c, r) in s.wakes {
+                    self.dirty[c].add(r.x0, r.y0, r.x1, r.y1);
+                }
+                for c in s.bumps {
+                    self.versions[c] = self.versions[c].wrapping_add(1);
+                }
+                for (x, y) in s.moves {
+                    // SAFETY: the patches are done with the moved map
+                    unsafe { mark_moved(self.moved.as_mut_ptr(), self.moved_on.as_mut_ptr(), self.w, self.tick as u32, x, y) };
+                }
+                self.condensed += s.condensed;
+                self.slow[s.chunk].add(s.slow.x0, s.slow.y0, s.slow.x1, s.slow.y1);
+            }
+        }
+    }
+
+    /// Which pass a chunk is updated in, 0 to 8
+    fn pass_of(&self, c: usize) -> usize {
+        let wraps_x = matches!(self.topology, Topology::WrapX | Topology::Wrap);
+        let wraps_y = self.topology == Topology::Wrap;
+        return colour(c % self.cw, self.cw, self.w, wraps_x)
+            + 3 * colour(c / self.cw, self.ch, self.h, wraps_y);
+    }
+
+    /// Splits the ground into patches for the given chunks of one pass to
+    /// be updated at the same time
+    fn patches(&mut self, chunks: &[usize]) -> Vec<Patch<'_>> {
+        let cells = self.cells.as_mut_ptr();
+        let data = self.data.as_mut_ptr();
+        let shade = self.shade.as_mut_ptr();
+        let moved = self.moved.as_mut_ptr();
+        let moved_on = self.moved_on.as_mut_ptr();
+        let seed = self.seed.wrapping_add(self.tick.wrapping_mul(0x9E3779B97F4A7C15));
+        return chunks.iter().map(|&c| Patch {
+            chunk: c,
+            stream: maf::hash(seed, self.origin.div_euclid(CHUNK as i32) + (c % self.cw) as i32, (c / self.cw) as i32) as u64,
+            awake: self.awake[c],
+            dirty: Region::EMPTY,
+            slow: Region::EMPTY,
+            slow_tick: self.tick.is_multiple_of(SLOW),
+            w: self.w,
+            h: self.h,
+            cw: self.cw,
+            origin: self.origin,
+            topology: self.topology,
+            seed: self.seed,
+            tick: self.tick as u32,
+            temperature: self.temperature,
+            daylight: self.daylight,
+            warm: self.warm,
+            wind: &self.wind,
+            sky: &self.sky,
+            cells,
+            data,
+            shade,
+            moved,
+            moved_on,
+            spill: Spill { chunk: c, ..Spill::default() },
+        }).collect();
+    }
+
+    /// Works out where the open sky ends in columns of chunks that have
+    /// changed, so updates needn't look all the way up
+    fn look_up(&mut self) {
+        for cx in 0..self.cw {
+            let v = (0..self.ch).fold(0u32, |v, cy| v.wrapping_add(self.versions[cy * self.cw + cx]));
+            if v == self.sky_seen[cx] {
+                continue;
+            }
+            self.sky_seen[cx] = v;
+            for lx in cx * CHUNK..((cx + 1) * CHUNK).min(self.w) {
+                let mut y = 0;
+                while y < self.h && self.cells[y * self.w + lx] == CellType::Empty {
+                    y += 1;
+                }
+                self.sky[lx] = y as i32;
+            }
         }
+    }
 
-        //for i in 0..self.cells.len() {
-            //self.cells[i] = self.buf[i];
+    /// Index of the cell at (x, y), wrapping round whichever edges the
+    /// topology joins up. None if it's off a walled edge or outside the
+    /// loaded window.
+    pub fn index(&self, x: i32, y: i32) -> Option<usize> {
+        return index_in(self.w, self.h, self.topology, self.origin, x, y);
+    }
+
+    pub fn get_cell(&self, x: i32, y: i32) -> CellType {
+        match self.index(x, y) {
+            Some(i) => self.cells[i],
+            None => CellType::Bedrock,
+        }
+    }
+
+    pub fn get_data(&self, x: i32, y: i32) -> u8 {
+        match self.index(x, y) {
+            Some(i) => self.data[i],
+            None => 0,
+        }
+    }
+
+    pub fn set_cell(&mut self, x: i32, y: i32, val: CellType) -> bool {
+        return self.set_cell_with(x, y, val, 0);
+    }
+
+    pub fn set_cell_with(&mut self, x: i32, y: i32, val: CellType, data: u8) -> bool {
+        let Some(cell) = self.index(x, y) else {
+            return false;
+        };
+        let (x, y) = (cell % self.w, cell / self.w);
+        //let moved = self.moved[cell];
+        //if !moved {
+         if self.cells[cell] != val {
+             self.shade[cell] = maf::hash(self.seed, self.origin + x as i32, y as i32) as u8;
+         }
+         self.cells[cell] = val;
+         self.data[cell] = data;
+         let chunk = (y / CHUNK) * self.cw + x / CHUNK;
+         self.versions[chunk] = self.versions[chunk].wrapping_add(1);
+         // SAFETY: the ground's borrowed mutably
+         unsafe { mark_moved(self.moved.as_mut_ptr(), self.moved_on.as_mut_ptr(), self.w, self.tick as u32, x, y) };
+         self.wake(x as i32, y as i32);
         //}
+        //return !moved;
+        return true;
+    }
+
+    /// Marks the cell at window coordinates (x, y) and the cells around it
+    /// to be updated next tick
+    fn wake(&mut self, x: i32, y: i32) {
+        let dirty = &mut self.dirty;
+        around(self.w, self.h, self.cw, self.topology, x, y, |c, r| dirty[c].add(r.x0, r.y0, r.x1, r.y1));
+    }
+
+    /// Wakes every cell, for when the ground's been changed wholesale
+    pub fn wake_all(&mut self) {
+        let c = CHUNK as i32;
+        for cy in 0..self.ch {
+            for cx in 0..self.cw {
+                let x0 = cx as i32 * c;
+                let y0 = cy as i32 * c;
+                self.dirty[cy * self.cw + cx] = Region {
+                    x0,
+                    y0,
+                    x1: (x0 + c).min(self.w as i32) - 1,
+                    y1: (y0 + c).min(self.h as i32) - 1,
+                };
+            }
+        }
+    }
+
+    /// True if the cell was set this tick
+    pub fn has_moved(&self, x: i32, y: i32) -> bool {
+        return match self.index(x, y) {
+            Some(i) => {
+                // SAFETY: the ground's borrowed
+                unsafe { was_moved(self.moved.as_ptr(), self.moved_on.as_ptr(), self.w, self.tick as u32, i % self.w, i / self.w) }
+            }
+            None => false,
+        };
+    }
+
+    /// Wind at a cell, in world coordinates
+    pub fn wind_at(&self, x: i32, y: i32) -> Vec2 {
+        return self.wind.at(x - self.origin, y);
+    }
+
+    /// True if the column is loaded and its chunk is being updated
+    pub fn is_active(&self, x: i32) -> bool {
+        let lx = x - self.origin;
+        return lx >= 0 && lx < self.w as i32 && self.active[lx as usize / CHUNK];
+    }
+
+    /// Slides the window `chunks` chunks to the right (or left, if
+    /// negative). Columns sliding in are left empty and inactive.
+    pub fn shift(&mut self, chunks: i32) {
+        let n = chunks.unsigned_abs() as usize * CHUNK;
+        let w = self.w;
+        for y in 0..self.h {
+            let row = y * w..(y + 1) * w;
+            let cells = &mut self.cells[row.clone()];
+            let (data, shade) = (&mut self.data[row.clone()], &mut self.shade[row]);
+            if n >= w {
+                cells.fill(CellType::Empty);
+                data.fill(0);
+            } else if chunks > 0 {
+                cells.copy_within(n.., 0);
+                data.copy_within(n.., 0);
+                shade.copy_within(n.., 0);
+                cells[w - n..].fill(CellType::Empty);
+                data[w - n..].fill(0);
+            } else {
+                cells.copy_within(..w - n, n);
+                data.copy_within(..w - n, n);
+                shade.copy_within(..w - n, n);
+                cells[..n].fill(CellType::Empty);
+                data[..n].fill(0);
+            }
+        }
+        let k = (chunks.unsigned_abs() as usize).min(self.cw);
+        if chunks > 0 {
+            self.active.rotate_left(k);
+            self.active[self.cw - k..].fill(false);
+        } else {
+            self.active.rotate_right(k);
+            self.active[..k].fill(false);
+        }
+        self.origin += chunks * CHUNK as i32;
+        self.moved.fill(0);
+        self.cost.fill(0);
+        self.slow.fill(Region::EMPTY);
+        for v in self.versions.iter_mut() {
+            *v = v.wrapping_add(1);
+        }
+        self.wake_all();
+    }
+}
+
+/// The part of the ground one thread updates: a chunk and the cells within
+/// REACH of it, which it reads and writes through raw pointers into the
+/// ground's vectors. The patches of a pass are disjoint: their chunks have
+/// a whole chunk between them (see `pass_of`) and REACH is under half a
+/// chunk, so no cell is in two of them. A patch only marks its own chunk's
+/// rows of the moved map and spills the rest, along with everything else
+/// that isn't a cell.
+pub struct Patch<'a> {
+    chunk: usize,
+    /// Seeds the random stream the chunk draws from
+    stream: u64,
+    /// The cells to update, in window coordinates
+    awake: Region,
+    /// The chunk's own cells to update next tick
+    dirty: Region,
+    /// The chunk's own cells waiting on slow changes
+    slow: Region,
+    /// Whether slow changes are tried this tick
+    slow_tick: bool,
+    w: usize,
+    h: usize,
+    cw: usize,
+    origin: i32,
+    topology: Topology,
+    seed: u64,
+    tick: u32,
+    temperature: f32,
+    pub daylight: f32,
+    warm: bool,
+    wind: &'a Wind,
+    sky: &'a [i32],
+    cells: *mut CellType,
+    data: *mut u8,
+    shade: *mut u8,
+    moved: *mut Moved,
+    moved_on: *mut u32,
+    spill: Spill,
+}
+
+// SAFETY: the patches updated at the same time don't overlap, see Patch
+unsafe impl Send for Patch<'_> {}
+
+impl Patch<'_> {
+    /// Updates the awake part of the chunk, bottom row first
+    fn update(mut self) -> Spill {
+        let start = Instant::now();
+        let r = self.awake;
+        maf::with_stream(self.stream, || {
+            for y in (r.y0.max(0)..=r.y1).rev() {
+                for lx in r.x0..=r.x1 {
+                    self.update_cell(lx as usize, y);
+                }
+            }
+        });
+        if !self.dirty.is_empty() {
+            self.spill.wakes.push((self.chunk, self.dirty));
+        }
+        self.spill.slow = self.slow;
+        self.spill.cost = start.elapsed().as_nanos().min(u32::MAX as u128) as u32;
+        return self.spill;
     }
 
     fn swap(&mut self, x: i32, y: i32, xo:i32, yo: i32) {
         let src = self.get_cell(x, y);
         let dst = self.get_cell(x+xo, y+yo);
-        if self.set_cell(x + xo, y + yo, src) {
-          self.set_cell(x, y, dst);
+        let src_data = self.get_data(x, y);
+        let dst_data = self.get_data(x+xo, y+yo);
+        let (Some(a), Some(b)) = (self.index(x, y), self.index(x + xo, y + yo)) else {
+            return;
+        };
+        // SAFETY: both are within reach, see Patch
+        let (src_shade, dst_shade) = unsafe { (*self.shade.add(a), *self.shade.add(b)) };
+        self.set_cell_with(x + xo, y + yo, src, src_data);
+        self.set_cell_with(x, y, dst, dst_data);
+        unsafe {
+            *self.shade.add(a) = dst_shade;
+            *self.shade.add(b) = src_shade;
         }
     }
 
-    pub fn update(&mut self) {
-        for i in 0..self.cells.len() {
-            self.moved[i] = false;
+
+    /// Might the cell change this tick without anything around it changing?
+    fn is_restless(&self, cell: CellType, x: i32, y: i32) -> bool {
+        match cell {
+            CellType::Tree | CellType::Leaf | CellType::Grass | CellType::Moss |
+            CellType::Seed | CellType::Fire | CellType::Lava | CellType::Vapour => true,
+            CellType::Snow => self.warm,
+            CellType::Water => self.get_cell(x, y + 1) == CellType::Wood,
+            _ => false,
         }
+    }
 
-        for y in (0..self.h as i32).rev() {
-            for x in 0..self.w as i32 {
-                let cell = self.get_cell(x, y);
-                //println!("{} {} {}", x, y, cell);
+    /// Might the cell change by itself, but only slowly (see SLOW)?
+    fn is_slow(&self, cell: CellType, x: i32, y: i32) -> bool {
+        match cell {
+            CellType::Water => self.warm && self.get_cell(x, y - 1) == CellType::Empty,
+            CellType::Soil => self.get_cell(x, y - 1) == CellType::Empty,
+            _ => false,
+        }
+    }
 
-                if cell == CellType::Empty { continue; }
-                if cell == CellType::Wood { continue; }
+    fn update_cell(&mut self, lx: usize, y: i32) {
+        let x = self.origin + lx as i32;
+        let cell = self.get_cell(x, y);
+        //println!("{} {} {}", x, y, cell);
 
-                let i = y as usize * self.w + x as usize;
-                if self.moved[i] {
-                    //println!("moved");
-                    continue;
-                }
+        if cell == CellType::Empty { return; }
+        if cell == CellType::Wood || cell == CellType::Stone || cell == CellType::Ore { return; }
 
+        let i = y as usize * self.w + lx;
+        // SAFETY: the cell's in the patch's own chunk
+        if unsafe { was_moved(self.moved, self.moved_on, self.w, self.tick, lx, y as usize) } {
+            return;
+        }
+        if self.is_restless(cell, x, y) {
+            self.dirty.add(lx as i32, y, lx as i32, y);
+        } else if self.is_slow(cell, x, y) {
+            self.slow.add(lx as i32, y, lx as i32, y);
+        }
 
-                // Trees grow up
-                if cell == CellType::Tree {
-                    let cell_u = self.get_cell(x, y-1);
-                    if cell_u == CellType::Empty {
-                        if one_in(10) {
-                            self.set_cell(x, y - 1, CellType::Tree);
-                        }
-                        continue;
-                    }
+        // Trees and leaves grow, seed and wither
+        if cell == CellType::Tree || cell == CellType::Leaf {
+            plant::update(self, x, y, cell);
+            return;
+        }
 
-                    continue;
-                }
+        // Grass and moss spread over surfaces, grass can still fall
+        if cell == CellType::Grass && plant::update_grass(self, x, y) {
+            return;
+        }
+        if cell == CellType::Moss {
+            plant::update_moss(self, x, y);
+            return;
+        }
+        if cell == CellType::Vapour {
+            self.rise(x, y);
+            return;
+        }
+        if cell == CellType::Fire {
+            fire::update_fire(self, x, y);
+            return;
+        }
+        if cell == CellType::Lava && fire::update_lava(self, x, y) {
+            return;
+        }
 
+        // Water open to the air evaporates, faster when it's warm
+        if cell == CellType::Water && self.slow_tick && self.temperature > 0.0
+            && self.get_cell(x, y-1) == CellType::Empty
+            && chance(self.temperature * SLOW as f32 / 60000.0) {
+            self.set_cell(x, y, CellType::Vapour);
+            return;
+        }
 
-                let cell_d = self.get_cell(x, y+1);
+        // Snow settles like sand, and melts once it's warm
+        if cell == CellType::Snow && self.temperature > 0.0
+            && chance(self.temperature / 4000.0) {
+            self.set_cell(x, y, CellType::Water);
+            return;
+        }
+        if cell == CellType::Soil && self.slow_tick && one_in(2000 / SLOW as i32) {
+            plant::seed_grass(self, x, y);
+        }
+        if cell == CellType::Water && one_in(500) {
+            plant::seed_moss(self, x, y + 1);
+        }
 
-                // Everything else falls down...
-                if cell_d == CellType::Empty {
-                    self.swap(x, y, 0, 1);
-                    continue;
-                }
+        // Seeds resting on soil may sprout, otherwise they fall
+        if cell == CellType::Seed && plant::sprout(self, x, y) {
+            return;
+        }
 
-                if cell == CellType::AntiSand && cell_d != CellType::AntiSand {
-                    self.set_cell(x, y, CellType::Empty);
-                    self.set_cell(x, y+1, CellType::Empty);
-                    continue;
-                }
 
-                let dir = if i % 2 == 0 { -1} else {1};
-                let cell_l = self.get_cell(x-1, y);
-                let cell_r = self.get_cell(x+1, y);
-                let cell_bl = if is_free(cell_l) { self.get_cell(x-1, y+1) } else { CellType::Bedrock } ;
-                let cell_br = if is_free(cell_r) { self.get_cell(x+1, y+1) } else { CellType::Bedrock };
-
-                if cell != CellType::Water {
-                    match (cell_bl, cell_br) {
-                        (CellType::Empty, CellType::Empty) => {
-                            self.swap(x, y, dir, 1);
-                        },
-                        (_, CellType::Empty) => {
-                            self.swap(x, y, 1, 1);
-                        },
-                        (CellType::Empty, _) => {
-                            self.swap(x, y, -1, 1);
-                        },
-                        _ => {
-                            continue
-                        }
-                    }
-                } else {
-                    match (cell_bl, cell_br) {
-                        (CellType::Empty, CellType::Empty) => {
-                            self.swap(x, y, dir, 1);
-                        },
-                        (_, CellType::Empty) => {
-                            self.swap(x, y, 1, 1);
-                        },
-                        (CellType::Empty, _) => {
-                            self.swap(x, y, -1, 1);
-                        },
-                        _ => {
-                            if cell_l == CellType::Empty && cell_r == CellType::Empty {
-                                self.swap(x, y, dir, 0);
-                            } else if cell_l == CellType::Empty {
-                                self.swap(x, y, -1, 0);
-                            } else {
-                                self.swap(x, y, 1, 0);
-                            }
-                        }
+        let cell_d = self.get_cell(x, y+1);
+
+        // Everything else falls down...
+        if cell_d == CellType::Empty {
+            if !(is_light(cell) && self.drift(x, y)) {
+                self.swap(x, y, 0, 1);
+            }
+            return;
+        }
+
+        if cell == CellType::AntiSand && cell_d != CellType::AntiSand {
+            self.set_cell(x, y, CellType::Empty);
+            self.set_cell(x, y+1, CellType::Empty);
+            return;
+        }
+
+        let dir = if is_light(cell) {
+            self.wind_dir(x, y)
+        } else if i.is_multiple_of(2) { -1} else {1};
+        let cell_l = self.get_cell(x-1, y);
+        let cell_r = self.get_cell(x+1, y);
+        let cell_bl = if is_free(cell_l) { self.get_cell(x-1, y+1) } else { CellType::Bedrock } ;
+        let cell_br = if is_free(cell_r) { self.get_cell(x+1, y+1) } else { CellType::Bedrock };
+
+        if !is_liquid(cell) {
+            match (cell_bl, cell_br) {
+                (CellType::Empty, CellType::Empty) => {
+                    self.swap(x, y, dir, 1);
+                },
+                (_, CellType::Empty) => {
+                    self.swap(x, y, 1, 1);
+                },
+                (CellType::Empty, _) => {
+                    self.swap(x, y, -1, 1);
+                },
+                _ => {
+                    return
+                }
+            }
+        } else {
+            match (cell_bl, cell_br) {
+                (CellType::Empty, CellType::Empty) => {
+                    self.swap(x, y, dir, 1);
+                },
+                (_, CellType::Empty) => {
+                    self.swap(x, y, 1, 1);
+                },
+                (CellType::Empty, _) => {
+                    self.swap(x, y, -1, 1);
+                },
+                _ => {
+                    // Only ever into the empty, or water that's level would
+                    // keep swapping places and never let its chunk sleep
+                    if cell_l == CellType::Empty && cell_r == CellType::Empty {
+                        self.swap(x, y, dir, 0);
+                    } else if cell_l == CellType::Empty {
+                        self.swap(x, y, -1, 0);
+                    } else if cell_r == CellType::Empty {
+                        self.swap(x, y, 1, 0);
                     }
                 }
             }
         }
+    }
+
+    /// Picks left or right, leaning whichever way the wind blows
+    fn wind_dir(&self, x: i32, y: i32) -> i32 {
+        let v = self.wind_at(x, y).x;
+        return if chance(0.5 + v * 0.5) { 1 } else { -1 };
+    }
+
+    /// Strong wind blows falling light cells sideways as they fall
+    fn drift(&mut self, x: i32, y: i32) -> bool {
+        let v = self.wind_at(x, y).x;
+        if v.abs() < 0.3 || !chance(v.abs()) {
+            return false;
+        }
+        let xo = if v < 0.0 { -1 } else { 1 };
+        if self.get_cell(x + xo, y + 1) != CellType::Empty {
+            return false;
+        }
+        self.swap(x, y, xo, 1);
+        return true;
+    }
+
+    /// Gases drift upward, through liquids, and condense high in the sky
+    fn rise(&mut self, x: i32, y: i32) {
+        if y < CLOUD_LINE && one_in(20) {
+            self.set_cell(x, y, CellType::Empty);
+            self.spill.condensed += 1;
+            return;
+        }
+        let dir = self.wind_dir(x, y);
+        for (xo, yo) in [(0, -1), (dir, -1), (-dir, -1), (dir, 0), (-dir, 0)] {
+            let c = self.get_cell(x + xo, y + yo);
+            if c == CellType::Empty || (yo < 0 && c == CellType::Water) {
+                self.swap(x, y, xo, yo);
+                return;
+            }
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        return index_in(self.w, self.h, self.topology, self.origin, x, y);
+    }
 
+    /// Checks, in debug builds, that the cell at index i is within REACH of
+    /// the chunk, and so in no other patch
+    fn reach(&self, i: usize) -> usize {
+        let c = CHUNK as i32;
+        let near = |v: usize, chunk: usize, size: usize| {
+            // How far past the start of the chunk, round a wrapped edge
+            let d = (v as i32 - (chunk * CHUNK) as i32).rem_euclid(size as i32);
+            d < c + REACH as i32 || d >= size as i32 - REACH as i32
+        };
+        debug_assert!(near(i % self.w, self.chunk % self.cw, self.w) && near(i / self.w, self.chunk / self.cw, self.h),
+            "cell {} is out of reach of chunk {}", i, self.chunk);
+        return i;
     }
 
     pub fn get_cell(&self, x: i32, y: i32) -> CellType {
-        if x < 0 || x > (self.w - 1) as i32 || y < 0 || y > (self.h - 1) as i32 {
-            return CellType::Bedrock;
+        match self.index(x, y) {
+            // SAFETY: within reach, see Patch
+            Some(i) => unsafe { *self.cells.add(self.reach(i)) },
+            None => CellType::Bedrock,
+        }
+    }
+
+    pub fn get_data(&self, x: i32, y: i32) -> u8 {
+        match self.index(x, y) {
+            // SAFETY: within reach, see Patch
+            Some(i) => unsafe { *self.data.add(self.reach(i)) },
+            None => 0,
         }
-        return self.cells[y as usize * self.w + x as usize];
     }
 
     pub fn set_cell(&mut self, x: i32, y: i32, val: CellType) -> bool {
-        if x < 0 || x > (self.w - 1) as i32 || y < 0 || y > (self.h - 1) as i32 {
+        return self.set_cell_with(x, y, val, 0);
+    }
+
+    /// Sets a cell like Ground::set_cell_with, spilling what it does to
+    /// other chunks' bookkeeping
+    pub fn set_cell_with(&mut self, x: i32, y: i32, val: CellType, data: u8) -> bool {
+        let Some(cell) = self.index(x, y) else {
             return false;
+        };
+        let cell = self.reach(cell);
+        let (x, y) = (cell % self.w, cell / self.w);
+        // SAFETY: within reach, see Patch
+        unsafe {
+            if *self.cells.add(cell) != val {
+                *self.shade.add(cell) = maf::hash(self.seed, self.origin + x as i32, y as i32) as u8;
+            }
+            *self.cells.add(cell) = val;
+            *self.data.add(cell) = data;
         }
-        let cell = y as usize * self.w + x as usize;
-        //let moved = self.moved[cell];
-        //if !moved {
-         self.cells[cell] = val;
-         self.moved[cell] = true;// val != CellType::Empty;
-        //}
-        //return !moved;
+        let chunk = (y / CHUNK) * self.cw + x / CHUNK;
+        if chunk == self.chunk {
+            self.spill.sets += 1;
+            // SAFETY: the cell's in the patch's own chunk
+            unsafe { mark_moved(self.moved, self.moved_on, self.w, self.tick, x, y) };
+        } else {
+            self.spill.bumps.push(chunk);
+            self.spill.moves.push((x, y));
+        }
+        let (own, dirty, spill) = (self.chunk, &mut self.dirty, &mut self.spill);
+        around(self.w, self.h, self.cw, self.topology, x as i32, y as i32, |c, r| {
+            if c == own {
+                dirty.add(r.x0, r.y0, r.x1, r.y1);
+            } else {
+                spill.wakes.push((c, r));
+            }
+        });
         return true;
     }
+
+    /// True if nothing but air was between (x, y) and the sky at the start
+    /// of the tick
+    pub fn is_sky_exposed(&self, x: i32, y: i32) -> bool {
+        return match self.index(x, 0) {
+            Some(i) => self.sky[i] >= y,
+            None => false,
+        };
+    }
+
+    /// Looks for a cell of type `t` in the square of radius `r` around (x, y)
+    pub fn is_near(&self, x: i32, y: i32, r: i32, t: CellType) -> bool {
+        for j in -r..=r {
+            for i in -r..=r {
+                if self.get_cell(x + i, y + j) == t {
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+
+    /// Wind at a cell, in world coordinates
+    fn wind_at(&self, x: i32, y: i32) -> Vec2 {
+        return self.wind.at(x - self.origin, y);
+    }
 }
@@ -0,0 +1,24 @@
+#![allow(clippy::needless_return)]
+
+pub mod ground;
+pub mod agent;
+pub mod resources;
+pub mod maf;
+pub mod config;
+pub mod weather;
+pub mod wind;
+pub mod clock;
+pub mod save;
+pub mod plant;
+pub mod fire;
+pub mod light;
+pub mod render;
+pub mod terrain;
+pub mod caves;
+pub mod stream;
+pub mod camera;
+pub mod overlay;
+pub mod minimap;
+pub mod capture;
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,212 @@
+use macroquad::prelude::*;
+use crate::ground::{Ground, CellType, CHUNK, is_liquid};
+
+/// How far outside a chunk to look for light shining into it. Light fades
+/// out in open air well before this, so one chunk only lights its neighbours.
+const MARGIN: usize = 24;
+
+/// Lowest light level, so the darkest caves aren't pure black
+const AMBIENT: f32 = 0.06;
+
+/// Colour of light given off by fire and lava
+const GLOW: Color = Color::new(1.0, 0.75, 0.5, 1.0);
+
+pub fn emission(t: CellType) -> u8 {
+    match t {
+        CellType::Fire => 255,
+        CellType::Lava => 220,
+        _ => 0,
+    }
+}
+
+/// Light lost spreading into a cell
+fn cost(t: CellType) -> u8 {
+    match t {
+        CellType::Empty | CellType::Vapour | CellType::Fire => 12,
+        CellType::Leaf => 40,
+        t if is_liquid(t) => 28,
+        _ => 72,
+    }
+}
+
+/// Light lost shining straight down through a cell from the sky
+fn sky_loss(t: CellType) -> u8 {
+    match t {
+        CellType::Empty | CellType::Vapour | CellType::Fire => 0,
+        CellType::Water => 20,
+        CellType::Leaf => 48,
+        _ => 255,
+    }
+}
+
+/// Per-cell light levels from the sky and from glowing cells, kept apart so
+/// that the sky can dim at night while lava keeps glowing. Each chunk's
+/// light is kept until the ground in or around it changes.
+pub struct LightMap {
+    pub w: usize,
+    pub h: usize,
+    pub sky: Vec<u8>,
+    pub glow: Vec<u8>,
+    /// Sunlight falling straight down each column, before it spreads out
+    direct: Vec<u8>,
+    /// Ground chunk versions as of the last relight
+    seen: Vec<u32>,
+    /// Bumped every time a chunk is relit
+    pub versions: Vec<u32>,
+}
+
+impl LightMap {
+    pub fn new(ground: &Ground) -> LightMap {
+        let n = ground.w * ground.h;
+        LightMap {
+            w: ground.w,
+            h: ground.h,
+            sky: vec![0; n],
+            glow: vec![0; n],
+            direct: vec![0; n],
+            seen: vec![u32::MAX; ground.versions.len()],
+            versions: vec![0; ground.versions.len()],
+        }
+    }
+
+    pub fn update(&mut self, ground: &Ground) {
+        let cw = ground.cw;
+        let ch = ground.ch;
+        let mut stale = vec![false; cw * ch];
+        let mark = |stale: &mut Vec<bool>, cx: usize, cy_from: usize, cy_to: usize| {
+            for x in cx.saturating_sub(1)..(cx + 2).min(cw) {
+                for y in cy_from..cy_to {
+                    stale[y * cw + x] = true;
+                }
+            }
+        };
+
+        for cx in 0..cw {
+            let mut changed = false;
+            for cy in 0..ch {
+                let i = cy * cw + cx;
+                if ground.versions[i] != self.seen[i] {
+                    self.seen[i] = ground.versions[i];
+                    mark(&mut stale, cx, cy.saturating_sub(1), (cy + 2).min(ch));
+                    changed = true;
+                }
+            }
+            // Shadows fall all the way down, from the highest change in sunlight
+            if changed {
+                if let Some(y) = self.shine_down(ground, cx) {
+                    mark(&mut stale, cx, y / CHUNK, ch);
+                }
+            }
+        }
+
+        for cy in 0..ch {
+            for cx in 0..cw {
+                if stale[cy * cw + cx] {
+                    self.relight(ground, cx, cy);
+                }
+            }
+        }
+    }
+
+    /// Recomputes direct sunlight for a chunk's columns, returning the
+    /// highest row where it changed
+    fn shine_down(&mut self, ground: &Ground, cx: usize) -> Option<usize> {
+        let mut top: Option<usize> = None;
+        for x in cx * CHUNK..((cx + 1) * CHUNK).min(self.w) {
+            let mut s: u8 = 255;
+            for y in 0..self.h {
+                let i = y * self.w + x;
+                if self.direct[i] != s {
+                    self.direct[i] = s;
+                    top = Some(top.map_or(y, |t| t.min(y)));
+                }
+                s = s.saturating_sub(sky_loss(ground.cells[i]));
+            }
+        }
+        return top;
+    }
+
+    /// Spreads sky and glow light around a chunk and its margin, keeping
+    /// the result for the chunk itself
+    fn relight(&mut self, ground: &Ground, cx: usize, cy: usize) {
+        let x0 = (cx * CHUNK).saturating_sub(MARGIN);
+        let y0 = (cy * CHUNK).saturating_sub(MARGIN);
+        let x1 = ((cx + 1) * CHUNK + MARGIN).min(self.w);
+        let y1 = ((cy + 1) * CHUNK + MARGIN).min(self.h);
+        let rw = x1 - x0;
+        let rh = y1 - y0;
+
+        let mut sky = vec![0u8; rw * rh];
+        let mut glow = vec![0u8; rw * rh];
+        let mut costs = vec![0u8; rw * rh];
+        for y in 0..rh {
+            for x in 0..rw {
+                let gi = (y + y0) * self.w + x + x0;
+                let cell = ground.cells[gi];
+                sky[y * rw + x] = self.direct[gi];
+                glow[y * rw + x] = emission(cell);
+                costs[y * rw + x] = cost(cell);
+            }
+        }
+
+        // Two sweeps, down-right then up-left, each pulling light in from
+        // the neighbours already visited
+        let spread = |light: &mut Vec<u8>, i: usize, n: usize| {
+            let v = light[n].saturating_sub(costs[i]);
+            if v > light[i] {
+                light[i] = v;
+            }
+        };
+        for y in 0..rh {
+            for x in 0..rw {
+                let i = y * rw + x;
+                if x > 0 {
+                    spread(&mut sky, i, i - 1);
+                    spread(&mut glow, i, i - 1);
+                }
+                if y > 0 {
+                    spread(&mut sky, i, i - rw);
+                    spread(&mut glow, i, i - rw);
+                }
+            }
+        }
+        for y in (0..rh).rev() {
+            for x in (0..rw).rev() {
+                let i = y * rw + x;
+                if x + 1 < rw {
+                    spread(&mut sky, i, i + 1);
+                    spread(&mut glow, i, i + 1);
+                }
+                if y + 1 < rh {
+                    spread(&mut sky, i, i + rw);
+                    spread(&mut glow, i, i + rw);
+                }
+            }
+        }
+
+        for y in cy * CHUNK..((cy + 1) * CHUNK).min(self.h) {
+            for x in cx * CHUNK..((cx + 1) * CHUNK).min(self.w) {
+                let ri = (y - y0) * rw + x - x0;
+                self.sky[y * self.w + x] = sky[ri];
+                self.glow[y * self.w + x] = glow[ri];
+            }
+        }
+        let c = cy * self.w.div_ceil(CHUNK) + cx;
+        self.versions[c] = self.versions[c].wrapping_add(1);
+    }
+
+    /// Colour to multiply a cell by: sky light in the colour of the time of
+    /// day, or the warm light of something glowing, whichever is brighter
+    pub fn light(&self, i: usize, tint: Color) -> Color {
+        let s = self.sky[i] as f32 / 255.0;
+        let g = self.glow[i] as f32 / 255.0;
+        let c = |sky: f32, glow: f32| (s * sky).max(g * glow).max(AMBIENT);
+        return Color::new(c(tint.r, GLOW.r), c(tint.g, GLOW.g), c(tint.b, GLOW.b), 1.0);
+    }
+
+    pub fn light_at(&self, x: i32, y: i32, tint: Color) -> Color {
+        let x = x.clamp(0, self.w as i32 - 1) as usize;
+        let y = y.clamp(0, self.h as i32 - 1) as usize;
+        return self.light(y * self.w + x, tint);
+    }
+}
@@ -1,5 +1,134 @@
-use macroquad::prelude::*;
+use std::cell::Cell;
+
+thread_local! {
+    /// What one_in and friends draw from. Each thread has its own, so the
+    /// ground can give every chunk it updates a stream of its own.
+    static STREAM: Cell<Rng> = Cell::new(Rng::new(0));
+}
+
+fn draw<T>(f: impl FnOnce(&mut Rng) -> T) -> T {
+    return STREAM.with(|s| {
+        let mut rng = s.get();
+        let v = f(&mut rng);
+        s.set(rng);
+        return v;
+    });
+}
+
+/// Reseeds this thread's stream
+pub fn reseed(seed: u64) {
+    STREAM.with(|s| s.set(Rng::new(seed)));
+}
+
+/// Runs f drawing from a stream seeded with `seed`, then puts this
+/// thread's own stream back
+pub fn with_stream<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    let outer = STREAM.with(|s| s.replace(Rng::new(seed)));
+    let v = f();
+    STREAM.with(|s| s.set(outer));
+    return v;
+}
 
 pub fn one_in(num: i32) -> bool {
-    return rand::gen_range(0, num) == 1;
+    return draw(|r| r.one_in(num));
+}
+
+/// True with probability p
+pub fn chance(p: f32) -> bool {
+    return draw(|r| r.next_f32() < p);
+}
+
+/// Integer in [low, high)
+pub fn gen_range(low: i32, high: i32) -> i32 {
+    return draw(|r| r.range(low, high));
+}
+
+/// One of the 8 cells around (x, y), or (x, y) itself
+pub fn rand_neighbour(x: i32, y: i32) -> (i32, i32) {
+    return draw(|r| (x + r.range(0, 3) - 1, y + r.range(0, 3) - 1));
+}
+
+/// A small seedable PCG generator, for things that need to replay the same
+/// way from a seed without disturbing (or being disturbed by) anything else.
+#[derive(Copy, Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        return rng;
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        return xorshifted.rotate_right((old >> 59) as u32);
+    }
+
+    /// Float in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        return (self.next_u32() >> 8) as f32 / (1 << 24) as f32;
+    }
+
+    /// Integer in [low, high)
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        return low + (self.next_u32() % (high - low) as u32) as i32;
+    }
+
+    pub fn one_in(&mut self, num: i32) -> bool {
+        return self.range(0, num) == 0;
+    }
+}
+
+/// Mixes a seed and grid position into a well scrambled number
+pub fn hash(seed: u64, x: i32, y: i32) -> u32 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E3779B97F4A7C15)
+        ^ (y as u32 as u64).wrapping_mul(0xC2B2AE3D27D4EB4F);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D049BB133111EB);
+    return (h ^ (h >> 31)) as u32;
+}
+
+/// Hash as a float in [0, 1)
+pub fn hash_f32(seed: u64, x: i32, y: i32) -> f32 {
+    return (hash(seed, x, y) >> 8) as f32 / (1 << 24) as f32;
+}
+
+fn smooth(t: f32) -> f32 {
+    return t * t * (3.0 - 2.0 * t);
+}
+
+/// Smooth value noise in [0, 1), with features about one unit apart
+pub fn noise(seed: u64, x: f32, y: f32) -> f32 {
+    let ix = x.floor() as i32;
+    let iy = y.floor() as i32;
+    let fx = smooth(x - ix as f32);
+    let fy = smooth(y - iy as f32);
+    let top = hash_f32(seed, ix, iy) * (1.0 - fx) + hash_f32(seed, ix + 1, iy) * fx;
+    let bottom = hash_f32(seed, ix, iy + 1) * (1.0 - fx) + hash_f32(seed, ix + 1, iy + 1) * fx;
+    return top * (1.0 - fy) + bottom * fy;
+}
+
+/// Layers of noise at doubling frequencies and halving strength, in [0, 1)
+pub fn fbm(seed: u64, x: f32, y: f32, octaves: u32) -> f32 {
+    let mut total = 0.0;
+    let mut amp = 1.0;
+    let mut norm = 0.0;
+    let mut f = 1.0;
+    for o in 0..octaves {
+        total += noise(seed.wrapping_add(o as u64), x * f, y * f) * amp;
+        norm += amp;
+        amp *= 0.5;
+        f *= 2.0;
+    }
+    return total / norm;
 }
@@ -1,93 +1,228 @@
 
+#![allow(clippy::needless_return)]
+
 use macroquad::prelude::*;
 use macroquad::ui::{root_ui, hash};
-use ground::{Ground, CellType};
-use dino::{Dino, Dir, Job};
-use person::{Person};
+use pixmoosh::{camera, ground, agent, resources, maf, config, weather, wind, clock, save, light, render, terrain, stream, overlay, minimap, capture};
+use ground::{Ground, CellType, Topology, CHUNK};
+use agent::{Agent, Dir};
 use maf::one_in;
-use resources::load_resources;
-
-mod ground;
-mod dino;
-mod person;
-mod resources;
-mod maf;
-mod tests;
-
-pub const BG: Color = Color::new(0.0, 0.423, 0.493, 1.00);
-pub const WATER: Color = Color::new(0.325, 0.549, 0.549, 1.00);
-pub const SAND: Color = Color::new(0.862, 0.549, 0.227, 1.00);
-pub const SAND2: Color = Color::new(0.752, 0.49, 0.29, 1.00);
-pub const WOOD: Color = Color::new(0.678, 0.419, 0.282, 1.00);
-pub const TREE: Color = Color::new(0.3, 0.6, 0.2, 1.00);
+use resources::{load_resources, load_sheets};
+use config::Config;
+use weather::{Weather, Precip};
+use wind::Wind;
+use clock::{Clock, Pacer, TICK_RATE};
+use light::LightMap;
+use render::{BG, Canvas};
+use stream::Stream;
+use camera::Camera;
+use overlay::Overlays;
+use minimap::Minimap;
+use capture::Recorder;
 
 #[macroquad::main("Life")]
 async fn main() {
-    let w = screen_width() as usize;
-    let h = screen_height() as usize;
-
-    rand::srand(macroquad::miniquad::date::now() as _);
+    let config = Config::load(macroquad::miniquad::date::now() as _);
+    // The world stays this size however the window's resized
+    let w = config.width.unwrap_or(screen_width() as usize);
+    let h = config.height.unwrap_or(screen_height() as usize);
+    rand::srand(config.seed);
+    maf::reseed(config.seed);
 
     let mut selected = CellType::Sand;
+    let mut show_wind = false;
+    let mut show_map = true;
 
-    let resources = load_resources().await;
+    let mut species = agent::load(&config.species).unwrap_or_else(|e| {
+        eprintln!("Couldn't load species, using the built-in ones: {}", e);
+        agent::builtin()
+    });
+    let resources = match load_resources(&species).await {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Couldn't load sprites, using the built-in species: {}", e);
+            species = agent::builtin();
+            load_resources(&species).await.expect("built-in sprites")
+        }
+    };
 
-    let mut ground = Ground::new(w, h);
-    ground.init();
+    // An endless world keeps a window of it loaded around the camera,
+    // which starts out at world x 0
+    let mut camera = Camera::new();
+    let mut drag: Option<Vec2> = None;
+    let mut stream = None;
+    let mut ground;
+    if config.topology == Topology::Infinite {
+        ground = Ground::new(Stream::window(w), h);
+        ground.origin = -stream::MARGIN * CHUNK as i32;
+        let mut s = Stream::new(&format!("world-{}", config.seed), &config.terrain, config.seed);
+        s.fill(&mut ground);
+        stream = Some(s);
+    } else {
+        ground = Ground::new(w, h);
+        terrain::generate(&mut ground, &config.terrain, config.seed);
+    }
+    ground.wind = Wind::new(ground.w, h, config.seed);
+    ground.topology = config.topology;
 
-    let mut dinos: Vec<Dino> = Vec::new();
-    for _ in 0..5 {
-        let mut d = Dino::new(
-            rand::gen_range(0, w) as f32,
-            rand::gen_range(0, h /2) as f32,
-            rand::gen_range(10, 30) as f32 / 10.0);
-        if one_in(2) {
-            d.dir = Dir::West;
-        }
-        if one_in(2) {
-            d.job = Job::Idle;
-        }
-        dinos.push(d);
+    let mut weather = Weather::new(config.seed);
+    weather.closed = config.closed_water;
+    weather.water = config.sky_water;
+
+    let mut clock = Clock::new(config.day_length);
+    let mut pacer = Pacer::new();
+    if let Some(precip) = config.weather {
+        weather.set(precip, ground.w);
     }
 
-    let mut peeps: Vec<Person> = Vec::new();
-    for _ in 0..5 {
-        let mut d = Person::new(
-            rand::gen_range(0, w) as f32,
-            rand::gen_range(0, h /2) as f32,
-            rand::gen_range(10, 30) as f32 / 10.0);
-        if one_in(2) {
-            d.dir = Dir::West;
-        }
-        if one_in(2) {
-            d.job = Job::Idle;
-        }
-        peeps.push(d);
+    let mut agents: Vec<Agent> = agent::spawn(&species, w, h);
+
+    let mut lights = LightMap::new(&ground);
+    if stream.is_none() {
+        camera.fit(0.0, ground.w as f32, ground.h as f32, screen_width(), screen_height());
     }
+    let mut canvas = Canvas::new(ground.w, ground.h);
+    canvas.patterns = config.patterns;
+    let texture = Texture2D::from_image(&canvas.image);
+    texture.set_filter(FilterMode::Nearest);
+    let mut overlays = Overlays::new(ground.w, ground.h);
+    let mut minimap = Minimap::new(ground.w, ground.h);
 
-    let mut image = Image::gen_image_color(w as u16, h as u16, BLACK);
-    let texture = Texture2D::from_image(&image);
+    // The sprite sheets again as images, to put screenshots and recordings
+    // together from
+    let sheets = load_sheets(&species).map_err(|e| eprintln!("Can't take screenshots: {}", e)).ok();
+    let every = config.frame_every as u64;
+    let (gw, gh) = (ground.w as u16, ground.h as u16);
+    let start_recording = |path: &str| {
+        match Recorder::start(path, gw, gh, every as f32 / TICK_RATE) {
+            Ok(r) => {
+                println!("Recording to {}, F11 to stop", path);
+                Some(r)
+            }
+            Err(e) => {
+                eprintln!("Couldn't record to {}: {}", path, e);
+                None
+            }
+        }
+    };
+    let mut recorder = config.record.as_deref().and_then(start_recording);
+    let mut recorded = clock.tick / every;
 
     loop {
-        if is_mouse_button_down(MouseButton::Left) {
+        if is_key_pressed(KeyCode::F1) {
+            show_wind = !show_wind;
+        }
+        if is_key_pressed(KeyCode::F2) {
+            overlays.moved = !overlays.moved;
+        }
+        if is_key_pressed(KeyCode::F3) {
+            overlays.cost = !overlays.cost;
+        }
+        if is_key_pressed(KeyCode::F4) {
+            overlays.inspect = !overlays.inspect;
+        }
+        if is_key_pressed(KeyCode::M) {
+            show_map = !show_map;
+        }
+        if is_key_pressed(KeyCode::Space) {
+            pacer.paused = !pacer.paused;
+        }
+        if is_key_pressed(KeyCode::Period) {
+            pacer.step();
+        }
+        if is_key_pressed(KeyCode::Equal) {
+            pacer.faster();
+        }
+        if is_key_pressed(KeyCode::Minus) {
+            pacer.slower();
+        }
+        if is_key_pressed(KeyCode::F5) {
+            if let Err(e) = save::save(save::SAVE_FILE, &ground, &clock, &weather) {
+                eprintln!("Couldn't save {}: {}", save::SAVE_FILE, e);
+            }
+            if let Some(s) = &stream {
+                s.flush(&ground);
+            }
+        }
+        if is_key_pressed(KeyCode::F9) {
+            // A save only holds the window, so loading one into an endless
+            // world would leave the chunks paged out round it, and the
+            // agents, from a different one
+            if stream.is_some() {
+                eprintln!("Can't load {} into an endless world", save::SAVE_FILE);
+            } else if let Err(e) = save::load(save::SAVE_FILE, &mut ground, &mut clock, &mut weather) {
+                eprintln!("Couldn't load {}: {}", save::SAVE_FILE, e);
+            }
+        }
+
+        // Pan with the arrow keys or by dragging with the middle button, and
+        // zoom with the wheel or page up and down
+        let (sw, sh) = (screen_width(), screen_height());
+        let (mx, my) = mouse_position();
+        let pan = 12.0;
+        if is_key_down(KeyCode::Left) {
+            camera.pan(-pan, 0.0);
+        }
+        if is_key_down(KeyCode::Right) {
+            camera.pan(pan, 0.0);
+        }
+        if is_key_down(KeyCode::Up) {
+            camera.pan(0.0, -pan);
+        }
+        if is_key_down(KeyCode::Down) {
+            camera.pan(0.0, pan);
+        }
+        if is_mouse_button_down(MouseButton::Middle) {
+            if let Some(last) = drag {
+                camera.pan(last.x - mx, last.y - my);
+            }
+            drag = Some(vec2(mx, my));
+        } else {
+            drag = None;
+        }
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0.0 {
+            camera.zoom_by(wheel.signum() as i32, mx, my);
+        }
+        if is_key_pressed(KeyCode::PageUp) {
+            camera.zoom_by(1, sw / 2.0, sh / 2.0);
+        }
+        if is_key_pressed(KeyCode::PageDown) {
+            camera.zoom_by(-1, sw / 2.0, sh / 2.0);
+        }
+        if is_key_pressed(KeyCode::Home) && stream.is_none() {
+            camera.fit(0.0, ground.w as f32, ground.h as f32, sw, sh);
+        }
+        // Clicking on the minimap moves the camera there rather than painting
+        let map = minimap.rect(sw, sh);
+        let on_map = if show_map { minimap.to_world(map, mx, my) } else { None };
+        if let Some((x, y)) = on_map.filter(|_| is_mouse_button_down(MouseButton::Left)) {
+            let (vw, vh) = camera.view(sw, sh);
+            camera.x = x - vw / 2.0;
+            camera.y = y - vh / 2.0;
+        }
+        camera.clamp(ground.origin as f32, ground.w as f32, ground.h as f32, sw, sh, stream.is_some());
+        let view_x = camera.x.floor() as i32;
+        let view_w = camera.view(sw, sh).0.ceil() as i32;
+
+        if is_mouse_button_down(MouseButton::Left) && on_map.is_none() {
             //let is_shift = is_key_down(KeyCode::LeftShift);
             let c = selected;// if is_shift {CellType::Wood } else { CellType::Sand };
 
-            let (x, y) = mouse_position();
+            let (x, y) = camera.to_world(mx, my);
             let size = 8;
             for i in -size..size {
                 for j in -size..size {
-                    if ((i * i + j * j) as f32).sqrt() < size as f32 {
-                        if c == CellType::Wood || one_in(3) {
-                            ground.set_cell((x as i32)+i, (y as i32)-j, c);
-                        }
+                    if ((i * i + j * j) as f32).sqrt() < size as f32
+                        && (c == CellType::Wood || c == CellType::Stone || one_in(3)) {
+                        ground.set_cell((x as i32)+i, (y as i32)-j, c);
                     }
                 }
             }
         }
 
-        if is_mouse_button_down(MouseButton::Right) {
-            let (x, y) = mouse_position();
+        if is_mouse_button_down(MouseButton::Right) && on_map.is_none() {
+            let (x, y) = camera.to_world(mx, my);
             for i in -10..10 {
                 for j in -10..10 {
                     ground.set_cell((x as i32)+i, (y as i32)-j, CellType::AntiSand);
@@ -95,74 +230,121 @@ async fn main() {
             }
         }
 
-        ground.update();
+        if let Some(s) = &mut stream {
+            let xs: Vec<i32> = agents.iter().map(|a| a.centre(&species[a.species]).0 as i32).collect();
+            s.update(&mut ground, view_x, view_x + view_w, &xs);
+        }
 
-        for i in 0..ground.cells.len() {
-            //ground.cells[i] = ground.buf[i];
-            ground.moved[i] = false;
+        for _ in 0..pacer.ticks(get_frame_time()) {
+            clock.advance();
+            ground.daylight = clock.daylight();
+            weather.update(&mut ground);
+            ground.update();
 
-            image.set_pixel(
-                (i % w) as u32,
-                (i / w) as u32,
-                match ground.cells[i as usize] {
-                    CellType::Empty => BLANK,
-                    CellType::Bedrock => RED,
-                    CellType::Sand if i % 2 == 0 => SAND2,
-                    CellType::Sand => SAND,
-                    CellType::AntiSand => GREEN,
-                    CellType::Water => WATER,
-                    CellType::Wood => WOOD,
-                    CellType::Tree => TREE,
-                },
-            );
+            for a in agents.iter_mut() {
+                // Agents that wander out of the loaded world wait for it to come back
+                if stream.is_some() && !ground.is_active(a.centre(&species[a.species]).0 as i32) {
+                    continue;
+                }
+                for gc in a.step(&species[a.species], &ground, w, h) {
+                    ground.set_cell(gc.0, gc.1, gc.2);
+                }
+            }
         }
 
-        texture.update(&image);
-
-        clear_background(BG);
-        draw_texture(&texture, 0., 0., WHITE);
-        for d in dinos.iter_mut() {
-            let v = d.update(&ground, w, h);
+        lights.update(&ground);
+        let tint = clock.tint();
+        canvas.paint(&ground, &lights, tint, ground.origin);
+        canvas.upload(&texture);
 
-            for gc in v {
-                ground.set_cell(gc.0, gc.1, gc.2);
+        // F12 takes a screenshot and F11 starts and stops recording
+        if is_key_pressed(KeyCode::F11) {
+            match recorder.take() {
+                Some(r) => {
+                    let (path, frames) = (r.path.clone(), r.frames);
+                    match r.finish() {
+                        Ok(()) => println!("Recorded {} frames to {}", frames, path),
+                        Err(e) => eprintln!("Couldn't record to {}: {}", path, e),
+                    }
+                }
+                None => recorder = start_recording(&format!("recording-{}.gif", clock.tick)),
             }
-
-            draw_texture_ex(
-                &resources.dino,
-                d.x,
-                d.y,
-                WHITE,
-                DrawTextureParams {
-                    source: Some(d.sprite.frame().source_rect),
-                    dest_size: Some(d.sprite.frame().dest_size),
-                    flip_x: d.dir == Dir::West,
-                    ..Default::default()
+        }
+        let shoot = is_key_pressed(KeyCode::F12);
+        let due = recorder.is_some() && clock.tick / every != recorded;
+        if let (Some(sheets), true) = (&sheets, shoot || due) {
+            let frame = capture::compose(&canvas, &ground, &lights, tint, &agents, &species, sheets);
+            if shoot {
+                let path = format!("screenshot-{}.png", clock.tick);
+                match capture::save_png(&path, &frame) {
+                    Ok(()) => println!("Saved {}", path),
+                    Err(e) => eprintln!("Couldn't save {}: {}", path, e),
                 }
-            );
+            }
+            if let (Some(r), true) = (&mut recorder, due) {
+                recorded = clock.tick / every;
+                if let Err(e) = r.add(&frame) {
+                    eprintln!("Couldn't record to {}: {}", r.path, e);
+                    recorder = None;
+                }
+            }
         }
 
-        for d in peeps.iter_mut() {
-            let v = d.update(&ground, w, h);
-
-            for gc in v {
-                ground.set_cell(gc.0, gc.1, gc.2);
+        // Anything round the world, when it's smaller than the window, is left black
+        clear_background(BLACK);
+        // The world is drawn in world coordinates, the UI over it in screen ones
+        set_camera(&camera.camera2d(sw, sh));
+        draw_rectangle(ground.origin as f32, 0., ground.w as f32, ground.h as f32,
+            Color::new(BG.r * tint.r, BG.g * tint.g, BG.b * tint.b, 1.0));
+        draw_texture_ex(
+            &texture,
+            ground.origin as f32,
+            0.,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(ground.w as f32, ground.h as f32)),
+                ..Default::default()
             }
-
+        );
+        let dx = ground.origin as f32;
+        weather.draw(dx);
+        if show_wind {
+            ground.wind.draw(dx);
+        }
+        overlays.draw(&ground);
+        for a in agents.iter_mut() {
+            if !pacer.paused {
+                a.sprite.update();
+            }
+            let (cx, cy) = a.centre(&species[a.species]);
             draw_texture_ex(
-                &resources.walk,
-                d.x,
-                d.y,
-                WHITE,
+                &resources.sheets[a.species],
+                a.x,
+                a.y,
+                lights.light_at(cx as i32 - ground.origin, cy as i32, tint),
                 DrawTextureParams {
-                    source: Some(d.sprite.frame().source_rect),
-                    dest_size: Some(d.sprite.frame().dest_size),
-                    flip_x: d.dir == Dir::West,
+                    source: Some(a.sprite.frame().source_rect),
+                    dest_size: Some(a.sprite.frame().dest_size),
+                    flip_x: a.dir == Dir::West,
                     ..Default::default()
                 }
             );
         }
 
+        set_default_camera();
+        if overlays.inspect {
+            let (x, y) = camera.to_world(mx, my);
+            overlays.draw_inspector(&ground, &lights, x.floor() as i32, y.floor() as i32, mx, my);
+        }
+        if show_map {
+            minimap.update(&ground);
+            let (vw, vh) = camera.view(sw, sh);
+            minimap.draw(map, Rect::new(camera.x, camera.y, vw, vh));
+            for a in agents.iter() {
+                let (cx, cy) = a.centre(&species[a.species]);
+                minimap.mark(map, cx, cy, species[a.species].marker());
+            }
+        }
 
         let _ = root_ui()
             .style_builder()
@@ -170,7 +352,7 @@ async fn main() {
             .font_size(30)
             .build();
 
-        root_ui().window(hash!(), Vec2::new(10., 10.), Vec2::new(150., 25.), |ui| {
+        root_ui().window(hash!(), Vec2::new(10., 10.), Vec2::new(310., 25.), |ui| {
             //let (mouse_wheel_x, _) = mouse_wheel();
             ui.same_line(0.0);
             if ui.button(None, "Sand") {
@@ -188,7 +370,72 @@ async fn main() {
             if ui.button(None, "Tree") {
                 selected = CellType::Tree;
             }
+            ui.same_line(130.);
+            if ui.button(None, "Soil") {
+                selected = CellType::Soil;
+            }
+            ui.same_line(160.);
+            if ui.button(None, "Seed") {
+                selected = CellType::Seed;
+            }
+            ui.same_line(195.);
+            if ui.button(None, "Fire") {
+                selected = CellType::Fire;
+            }
+            ui.same_line(225.);
+            if ui.button(None, "Lava") {
+                selected = CellType::Lava;
+            }
+            ui.same_line(260.);
+            if ui.button(None, "Stone") {
+                selected = CellType::Stone;
+            }
+
+        });
 
+        root_ui().window(hash!(), Vec2::new(330., 10.), Vec2::new(150., 25.), |ui| {
+            ui.same_line(0.0);
+            if ui.button(None, "Auto") {
+                weather.auto = true;
+            }
+            ui.same_line(35.);
+            if ui.button(None, "Clear") {
+                weather.set(Precip::Clear, ground.w);
+            }
+            ui.same_line(75.);
+            if ui.button(None, "Rain") {
+                weather.set(Precip::Rain, ground.w);
+            }
+            ui.same_line(110.);
+            if ui.button(None, "Snow") {
+                weather.set(Precip::Snow, ground.w);
+            }
+        });
+
+        root_ui().window(hash!(), Vec2::new(490., 10.), Vec2::new(100., 25.), |ui| {
+            let (hh, mm) = clock.hh_mm();
+            ui.label(None, &format!("Day {} {:02}:{:02}", clock.day() + 1, hh, mm));
+        });
+
+        root_ui().window(hash!(), Vec2::new(600., 10.), Vec2::new(170., 25.), |ui| {
+            ui.same_line(0.0);
+            if ui.button(None, if pacer.paused { "Run" } else { "Pause" }) {
+                pacer.paused = !pacer.paused;
+            }
+            ui.same_line(45.);
+            if ui.button(None, "Step") {
+                pacer.step();
+            }
+            ui.same_line(80.);
+            if ui.button(None, "-") {
+                pacer.slower();
+            }
+            ui.same_line(95.);
+            ui.label(None, &format!("{}x", pacer.speed()));
+            ui.same_line(140.);
+            if ui.button(None, "+") {
+                pacer.faster();
+            }
         });
 
         next_frame().await
@@ -0,0 +1,139 @@
+use macroquad::prelude::*;
+use crate::ground::{Ground, CellType, CHUNK};
+use crate::render::cell_color;
+
+/// Widest the minimap is drawn, in screen pixels
+const MAX_WIDTH: usize = 256;
+
+/// Gap between the minimap and the edge of the screen
+const MARGIN: f32 = 10.0;
+
+/// A small picture of the whole ground, a pixel for every few cells, kept up
+/// to date a chunk at a time as the ground changes
+pub struct Minimap {
+    pub image: Image,
+    /// Cells across and down each pixel, a power of two no bigger than a chunk
+    pub scale: usize,
+    /// Ground versions of each chunk as last painted
+    seen: Vec<u32>,
+    origin: i32,
+    /// Painted since the texture was last updated
+    changed: bool,
+    texture: Option<Texture2D>,
+}
+
+impl Minimap {
+    pub fn new(w: usize, h: usize) -> Minimap {
+        let mut scale = 1;
+        while w.div_ceil(scale) > MAX_WIDTH && scale < CHUNK {
+            scale *= 2;
+        }
+        Minimap {
+            image: Image::gen_image_color(w.div_ceil(scale) as u16, h.div_ceil(scale) as u16, BLANK),
+            scale,
+            seen: Vec::new(),
+            origin: 0,
+            changed: false,
+            texture: None,
+        }
+    }
+
+    /// Repaints the chunks that have changed since last time, returning how
+    /// many there were
+    pub fn update(&mut self, ground: &Ground) -> usize {
+        if ground.origin != self.origin || self.seen.len() != ground.versions.len() {
+            self.origin = ground.origin;
+            self.seen = ground.versions.iter().map(|v| v.wrapping_sub(1)).collect();
+        }
+        let mut painted = 0;
+        for c in 0..self.seen.len() {
+            if self.seen[c] != ground.versions[c] {
+                self.seen[c] = ground.versions[c];
+                self.paint_chunk(ground, c % ground.cw, c / ground.cw);
+                painted += 1;
+            }
+        }
+        self.changed |= painted > 0;
+        return painted;
+    }
+
+    /// Colours each pixel of a chunk with the average of the cells it
+    /// covers, fading out where they're mostly empty
+    fn paint_chunk(&mut self, ground: &Ground, cx: usize, cy: usize) {
+        let s = self.scale;
+        let x0 = cx * CHUNK / s;
+        let y0 = cy * CHUNK / s;
+        let x1 = ((cx + 1) * CHUNK).min(ground.w).div_ceil(s);
+        let y1 = ((cy + 1) * CHUNK).min(ground.h).div_ceil(s);
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let (mut r, mut g, mut b, mut n, mut all) = (0.0, 0.0, 0.0, 0.0, 0.0);
+                for y in py * s..((py + 1) * s).min(ground.h) {
+                    for x in px * s..((px + 1) * s).min(ground.w) {
+                        all += 1.0;
+                        let i = y * ground.w + x;
+                        let cell = ground.cells[i];
+                        if cell == CellType::Empty {
+                            continue;
+                        }
+                        let c = cell_color(cell, ground.shade[i]);
+                        r += c.r;
+                        g += c.g;
+                        b += c.b;
+                        n += 1.0;
+                    }
+                }
+                let c = if n > 0.0 { Color::new(r / n, g / n, b / n, n / all) } else { BLANK };
+                self.image.set_pixel(px as u32, py as u32, c);
+            }
+        }
+    }
+
+    /// Where the minimap goes on a screen sw by sh pixels: the bottom right
+    /// corner
+    pub fn rect(&self, sw: f32, sh: f32) -> Rect {
+        let w = self.image.width as f32;
+        let h = self.image.height as f32;
+        return Rect::new(sw - w - MARGIN, sh - h - MARGIN, w, h);
+    }
+
+    /// World position under a point on the screen, if it's on the minimap
+    pub fn to_world(&self, r: Rect, sx: f32, sy: f32) -> Option<(f32, f32)> {
+        if !r.contains(vec2(sx, sy)) {
+            return None;
+        }
+        let s = self.scale as f32;
+        return Some((self.origin as f32 + (sx - r.x) * s, (sy - r.y) * s));
+    }
+
+    /// Screen position of a point in the world on the minimap
+    pub fn to_screen(&self, r: Rect, x: f32, y: f32) -> Vec2 {
+        let s = self.scale as f32;
+        return vec2(r.x + (x - self.origin as f32) / s, r.y + y / s);
+    }
+
+    /// Draws the map at r, in screen coordinates, with an outline round the
+    /// part of the world in view
+    pub fn draw(&mut self, r: Rect, view: Rect) {
+        let texture = self.texture.get_or_insert_with(|| Texture2D::from_image(&self.image));
+        if std::mem::take(&mut self.changed) {
+            texture.update(&self.image);
+        }
+        draw_rectangle(r.x - 2.0, r.y - 2.0, r.w + 4.0, r.h + 4.0, Color::new(0.0, 0.0, 0.0, 0.6));
+        draw_texture(texture, r.x, r.y, WHITE);
+
+        let a = self.to_screen(r, view.x, view.y);
+        let b = self.to_screen(r, view.right(), view.bottom());
+        let a = a.clamp(r.point(), r.point() + r.size());
+        let b = b.clamp(r.point(), r.point() + r.size());
+        draw_rectangle_lines(a.x, a.y, b.x - a.x, b.y - a.y, 1.0, WHITE);
+    }
+
+    /// Marks something at world (x, y) on the map
+    pub fn mark(&self, r: Rect, x: f32, y: f32, color: Color) {
+        let p = self.to_screen(r, x, y);
+        if r.contains(p) {
+            draw_rectangle(p.x - 1.5, p.y - 1.5, 3.0, 3.0, color);
+        }
+    }
+}
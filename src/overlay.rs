@@ -0,0 +1,108 @@
+use macroquad::prelude::*;
+use crate::ground::{Ground, CHUNK};
+use crate::light::LightMap;
+
+/// Colour cells set this tick are marked in
+const MOVED: [u8; 4] = [255, 0, 255, 160];
+
+/// Nanoseconds a chunk can take to update before it's drawn fully red
+const HOT: f32 = 200_000.0;
+
+/// Debug layers drawn over the world, each toggled by itself
+pub struct Overlays {
+    /// Cells set this tick
+    pub moved: bool,
+    /// How long each chunk took to update
+    pub cost: bool,
+    /// What's under the mouse
+    pub inspect: bool,
+    /// The moved map, a pixel per cell of the ground
+    pub image: Image,
+    texture: Option<Texture2D>,
+}
+
+impl Overlays {
+    pub fn new(w: usize, h: usize) -> Overlays {
+        Overlays {
+            moved: false,
+            cost: false,
+            inspect: false,
+            image: Image::gen_image_color(w as u16, h as u16, BLANK),
+            texture: None,
+        }
+    }
+
+    /// Marks the cells set this tick on the moved map
+    pub fn paint_moved(&mut self, ground: &Ground) {
+        for y in 0..ground.h {
+            for x in 0..ground.w {
+                let set = ground.has_moved(ground.origin + x as i32, y as i32);
+                let i = (y * ground.w + x) * 4;
+                self.image.bytes[i..i + 4].copy_from_slice(if set { &MOVED } else { &[0; 4] });
+            }
+        }
+    }
+
+    /// Draws the layers that are on, in world coordinates
+    pub fn draw(&mut self, ground: &Ground) {
+        let x0 = ground.origin as f32;
+        if self.moved {
+            self.paint_moved(ground);
+            let texture = self.texture.get_or_insert_with(|| {
+                let t = Texture2D::from_image(&self.image);
+                t.set_filter(FilterMode::Nearest);
+                t
+            });
+            texture.update(&self.image);
+            draw_texture(texture, x0, 0.0, WHITE);
+        }
+        if self.cost {
+            let c = CHUNK as f32;
+            for cy in 0..ground.ch {
+                for cx in 0..ground.cw {
+                    let x = x0 + cx as f32 * c;
+                    let y = cy as f32 * c;
+                    let heat = (ground.cost[cy * ground.cw + cx] as f32 / HOT).min(1.0);
+                    if heat > 0.0 {
+                        draw_rectangle(x, y, c, c, Color::new(1.0, 1.0 - heat, 0.0, 0.15 + 0.45 * heat));
+                    }
+                    draw_rectangle_lines(x, y, c, c, 1.0, Color::new(1.0, 1.0, 1.0, 0.2));
+                }
+            }
+        }
+    }
+
+    /// Draws what's at the world cell (x, y) in a box by the screen point
+    /// sx, sy, in screen coordinates
+    pub fn draw_inspector(&self, ground: &Ground, lights: &LightMap, x: i32, y: i32, sx: f32, sy: f32) {
+        let lines = describe(ground, lights, x, y);
+        let size = 16.0;
+        let w = lines.iter().map(|l| measure_text(l, None, size as u16, 1.0).width).fold(0.0, f32::max);
+        let h = lines.len() as f32 * size;
+        // Keep the box on the screen
+        let bx = (sx + 12.0).min(screen_width() - w - 8.0);
+        let by = (sy + 12.0).min(screen_height() - h - 8.0);
+        draw_rectangle(bx, by, w + 8.0, h + 6.0, Color::new(0.0, 0.0, 0.0, 0.75));
+        for (n, l) in lines.iter().enumerate() {
+            draw_text(l, bx + 4.0, by + size * (n as f32 + 1.0), size, WHITE);
+        }
+    }
+}
+
+/// Lines describing the world cell (x, y): its material and state, the
+/// light on it and the air around it. There's no temperature or pressure
+/// per cell, so it gives the air temperature everywhere shares.
+pub fn describe(ground: &Ground, lights: &LightMap, x: i32, y: i32) -> Vec<String> {
+    let mut lines = vec![format!("{:?} at {}, {}", ground.get_cell(x, y), x, y)];
+    let Some(i) = ground.index(x, y) else {
+        return lines;
+    };
+    lines.push(format!("data {} shade {}{}", ground.data[i], ground.shade[i],
+        if ground.has_moved(x, y) { ", moved" } else { "" }));
+    let c = i / ground.w / CHUNK * ground.cw + i % ground.w / CHUNK;
+    lines.push(format!("chunk {} took {}us", c, ground.cost[c] / 1000));
+    lines.push(format!("sky light {} glow {}", lights.sky[i], lights.glow[i]));
+    let wind = ground.wind_at(x, y);
+    lines.push(format!("air {:.1}C wind {:.2}, {:.2}", ground.temperature, wind.x, wind.y));
+    return lines;
+}
@@ -0,0 +1,239 @@
+use crate::ground::{Patch, CellType, REACH, is_soil, is_solid};
+use crate::maf::{one_in, chance, gen_range, rand_neighbour};
+
+/// How far a root, seed or patch of grass looks for water
+const THIRST_RADIUS: i32 = 6;
+
+pub struct Species {
+    /// Trunk length in cells (at most 31, it's packed in 5 bits)
+    pub height: u8,
+    /// Radius of the leaf canopy grown at the top of the trunk
+    pub canopy: i32,
+    /// One-in chance of a branch forking off each growth step
+    pub branch: i32,
+    /// One-in chance per tick of a leaf dropping a seed
+    pub seed: i32,
+}
+
+pub const SPECIES: [Species; 3] = [
+    // Oak
+    Species { height: 24, canopy: 6, branch: 6, seed: 4000 },
+    // Pine
+    Species { height: 31, canopy: 3, branch: 12, seed: 3000 },
+    // Shrub
+    Species { height: 6, canopy: 3, branch: 3, seed: 2000 },
+];
+
+// Leaves look canopy + 1 cells away for their tree, and moss looks for
+// water around a neighbour, neither of which may go past the ground's reach
+const _: () = {
+    let mut i = 0;
+    while i < SPECIES.len() {
+        assert!(SPECIES[i].canopy < REACH as i32);
+        i += 1;
+    }
+    assert!(THIRST_RADIUS < REACH as i32);
+};
+
+// Tree and leaf cells keep their species in the top 3 bits of the cell
+// data, and trees keep the length left to grow in the bottom 5.
+pub fn pack(species: usize, growth: u8) -> u8 {
+    return (species as u8) << 5 | growth.min(31);
+}
+
+pub fn species_of(data: u8) -> usize {
+    return (data >> 5) as usize % SPECIES.len();
+}
+
+fn growth_of(data: u8) -> u8 {
+    return data & 31;
+}
+
+// Seeds store species + 1, so that a plain seed (0) is a random species
+pub fn seed_data(species: usize) -> u8 {
+    return species as u8 + 1;
+}
+
+/// Seeds resting on soil with water nearby turn into a tree trunk.
+/// Returns true if the seed should stay put this tick.
+pub fn sprout(ground: &mut Patch, x: i32, y: i32) -> bool {
+    if !is_soil(ground.get_cell(x, y + 1)) {
+        return false;
+    }
+    if one_in(20) && ground.is_near(x, y, THIRST_RADIUS, CellType::Water) {
+        let data = ground.get_data(x, y);
+        let species = if data == 0 {
+            gen_range(0, SPECIES.len() as i32) as usize
+        } else {
+            (data as usize - 1) % SPECIES.len()
+        };
+        ground.set_cell_with(x, y, CellType::Tree, pack(species, SPECIES[species].height));
+    }
+    return true;
+}
+
+/// Growth slows with the light and stops at night
+fn sunny(ground: &Patch) -> bool {
+    return chance(ground.daylight);
+}
+
+pub fn update(ground: &mut Patch, x: i32, y: i32, cell: CellType) {
+    if cell == CellType::Tree {
+        update_tree(ground, x, y);
+    } else {
+        update_leaf(ground, x, y);
+    }
+}
+
+fn update_tree(ground: &mut Patch, x: i32, y: i32) {
+    let data = ground.get_data(x, y);
+    let species = species_of(data);
+    let sp = &SPECIES[species];
+
+    let below = ground.get_cell(x, y + 1);
+    let below_l = ground.get_cell(x - 1, y + 1);
+    let below_r = ground.get_cell(x + 1, y + 1);
+
+    // Roots need water, everything else needs something living to hold on to
+    let alive = if is_soil(below) {
+        !one_in(40) || ground.is_near(x, y, THIRST_RADIUS, CellType::Water)
+    } else {
+        below == CellType::Tree || below_l == CellType::Tree || below_r == CellType::Tree
+    };
+    if !alive {
+        // Dead trees dry out into wood
+        ground.set_cell(x, y, CellType::Wood);
+        return;
+    }
+
+    let growth = growth_of(data);
+    if growth == 0 || !one_in(10) || !sunny(ground) {
+        return;
+    }
+
+    if growth == 1 {
+        grow_canopy(ground, x, y, species);
+        ground.set_cell_with(x, y, CellType::Tree, pack(species, 0));
+        return;
+    }
+
+    // Trunks grow straight up, branches keep heading away from the trunk
+    let xo = if below == CellType::Tree || is_soil(below) {
+        0
+    } else if below_l == CellType::Tree {
+        1
+    } else {
+        -1
+    };
+    // Leaves get pushed aside by the growing tip
+    let above = ground.get_cell(x + xo, y - 1);
+    if above != CellType::Empty && above != CellType::Leaf {
+        return;
+    }
+    ground.set_cell_with(x + xo, y - 1, CellType::Tree, pack(species, growth - 1));
+    ground.set_cell_with(x, y, CellType::Tree, pack(species, 0));
+
+    if xo == 0 && growth > 4 && one_in(sp.branch) {
+        let side = if one_in(2) { -1 } else { 1 };
+        if ground.get_cell(x + side, y - 1) == CellType::Empty {
+            ground.set_cell_with(x + side, y - 1, CellType::Tree, pack(species, growth / 2));
+        }
+    }
+}
+
+fn grow_canopy(ground: &mut Patch, x: i32, y: i32, species: usize) {
+    let r = SPECIES[species].canopy;
+    for j in -r..=r {
+        for i in -r..=r {
+            if i * i + j * j > r * r {
+                continue;
+            }
+            if ground.get_cell(x + i, y + j) == CellType::Empty {
+                ground.set_cell_with(x + i, y + j, CellType::Leaf, pack(species, 0));
+            }
+        }
+    }
+}
+
+fn update_leaf(ground: &mut Patch, x: i32, y: i32) {
+    let species = species_of(ground.get_data(x, y));
+    let sp = &SPECIES[species];
+
+    if one_in(8) && !ground.is_near(x, y, sp.canopy + 1, CellType::Tree) {
+        ground.set_cell(x, y, CellType::Empty);
+        return;
+    }
+
+    if one_in(sp.seed) && ground.get_cell(x, y + 1) == CellType::Empty {
+        ground.set_cell_with(x, y + 1, CellType::Seed, seed_data(species));
+    }
+}
+
+fn is_buried(ground: &Patch, x: i32, y: i32) -> bool {
+    let above = ground.get_cell(x, y - 1);
+    return is_solid(above) || above == CellType::Water;
+}
+
+/// Grass grows on top of soil that's open to the sky and near water.
+/// Returns true if the grass changed (and so shouldn't fall this tick).
+pub fn update_grass(ground: &mut Patch, x: i32, y: i32) -> bool {
+    let dry = one_in(40) && !ground.is_near(x, y, THIRST_RADIUS, CellType::Water);
+    if dry || is_buried(ground, x, y) {
+        ground.set_cell(x, y, CellType::Soil);
+        return true;
+    }
+
+    if one_in(20) && sunny(ground) && ground.is_sky_exposed(x, y) {
+        let (nx, ny) = rand_neighbour(x, y);
+        let n = ground.get_cell(nx, ny);
+        if (n == CellType::Soil || n == CellType::Sand)
+            && !is_buried(ground, nx, ny)
+            && ground.is_near(nx, ny, THIRST_RADIUS, CellType::Water) {
+            ground.set_cell(nx, ny, CellType::Grass);
+            return true;
+        }
+    }
+    return false;
+}
+
+fn is_exposed(ground: &Patch, x: i32, y: i32) -> bool {
+    return !is_solid(ground.get_cell(x - 1, y))
+        || !is_solid(ground.get_cell(x + 1, y))
+        || !is_solid(ground.get_cell(x, y - 1))
+        || !is_solid(ground.get_cell(x, y + 1));
+}
+
+/// Bare soil open to the sky and near water slowly greens over
+pub fn seed_grass(ground: &mut Patch, x: i32, y: i32) {
+    if !is_buried(ground, x, y)
+        && ground.is_sky_exposed(x, y)
+        && ground.is_near(x, y, THIRST_RADIUS, CellType::Water) {
+        ground.set_cell(x, y, CellType::Grass);
+    }
+}
+
+/// Wood that's been sitting in water grows moss
+pub fn seed_moss(ground: &mut Patch, x: i32, y: i32) {
+    if ground.get_cell(x, y) == CellType::Wood {
+        ground.set_cell(x, y, CellType::Moss);
+    }
+}
+
+/// Moss creeps over damp wood and doesn't need any light
+pub fn update_moss(ground: &mut Patch, x: i32, y: i32) {
+    let exposed = is_exposed(ground, x, y);
+    let dry = one_in(40) && !ground.is_near(x, y, THIRST_RADIUS, CellType::Water);
+    if dry || !exposed {
+        ground.set_cell(x, y, CellType::Wood);
+        return;
+    }
+
+    if one_in(30) {
+        let (nx, ny) = rand_neighbour(x, y);
+        if ground.get_cell(nx, ny) == CellType::Wood
+            && is_exposed(ground, nx, ny)
+            && ground.is_near(nx, ny, THIRST_RADIUS, CellType::Water) {
+            ground.set_cell(nx, ny, CellType::Moss);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use macroquad::prelude::*;
+use crate::ground::{Ground, CellType, CHUNK};
+use crate::light::LightMap;
+
+pub const BG: Color = Color::new(0.0, 0.423, 0.493, 1.00);
+pub const WATER: Color = Color::new(0.325, 0.549, 0.549, 1.00);
+pub const SAND: Color = Color::new(0.862, 0.549, 0.227, 1.00);
+pub const SAND2: Color = Color::new(0.752, 0.49, 0.29, 1.00);
+pub const WOOD: Color = Color::new(0.678, 0.419, 0.282, 1.00);
+pub const TREE: Color = Color::new(0.3, 0.6, 0.2, 1.00);
+pub const SOIL: Color = Color::new(0.45, 0.3, 0.2, 1.00);
+pub const LEAF: Color = Color::new(0.2, 0.5, 0.15, 1.00);
+pub const SEED: Color = Color::new(0.9, 0.8, 0.4, 1.00);
+pub const GRASS: Color = Color::new(0.4, 0.7, 0.2, 1.00);
+pub const MOSS: Color = Color::new(0.35, 0.45, 0.25, 1.00);
+pub const SNOW: Color = Color::new(0.95, 0.95, 1.0, 1.00);
+pub const VAPOUR: Color = Color::new(0.8, 0.85, 0.9, 0.35);
+pub const STONE: Color = Color::new(0.45, 0.45, 0.48, 1.00);
+pub const ORE: Color = Color::new(0.8, 0.7, 0.3, 1.00);
+pub const FIRE: Color = Color::new(1.0, 0.55, 0.1, 1.00);
+pub const LAVA: Color = Color::new(0.95, 0.3, 0.05, 1.00);
+
+const SANDS: [Color; 4] = [SAND, SAND2, Color::new(0.9, 0.6, 0.28, 1.0), Color::new(0.81, 0.52, 0.25, 1.0)];
+const SOILS: [Color; 3] = [SOIL, Color::new(0.4, 0.27, 0.18, 1.0), Color::new(0.5, 0.34, 0.22, 1.0)];
+const WATERS: [Color; 3] = [WATER, Color::new(0.31, 0.53, 0.55, 1.0), Color::new(0.34, 0.57, 0.56, 1.0)];
+const WOODS: [Color; 3] = [WOOD, Color::new(0.64, 0.4, 0.27, 1.0), Color::new(0.7, 0.44, 0.3, 1.0)];
+const TREES: [Color; 3] = [TREE, Color::new(0.27, 0.55, 0.18, 1.0), Color::new(0.33, 0.63, 0.22, 1.0)];
+const LEAVES: [Color; 4] = [LEAF, Color::new(0.18, 0.46, 0.13, 1.0), Color::new(0.24, 0.55, 0.18, 1.0), Color::new(0.15, 0.42, 0.12, 1.0)];
+const GRASSES: [Color; 3] = [GRASS, Color::new(0.36, 0.66, 0.18, 1.0), Color::new(0.45, 0.74, 0.24, 1.0)];
+const MOSSES: [Color; 3] = [MOSS, Color::new(0.32, 0.42, 0.22, 1.0), Color::new(0.38, 0.48, 0.27, 1.0)];
+const SNOWS: [Color; 2] = [SNOW, Color::new(0.91, 0.92, 0.98, 1.0)];
+const FIRES: [Color; 3] = [FIRE, Color::new(1.0, 0.45, 0.05, 1.0), Color::new(1.0, 0.7, 0.2, 1.0)];
+const LAVAS: [Color; 3] = [LAVA, Color::new(0.9, 0.25, 0.03, 1.0), Color::new(1.0, 0.4, 0.1, 1.0)];
+const STONES: [Color; 4] = [STONE, Color::new(0.42, 0.42, 0.45, 1.0), Color::new(0.5, 0.5, 0.52, 1.0), Color::new(0.38, 0.38, 0.41, 1.0)];
+const ORES: [Color; 2] = [ORE, Color::new(0.75, 0.63, 0.25, 1.0)];
+
+/// The colours a material's cells can be
+pub fn palette(cell: CellType) -> &'static [Color] {
+    match cell {
+        CellType::Empty => &[BLANK],
+        CellType::Bedrock => &[RED],
+        CellType::Sand => &SANDS,
+        CellType::Soil => &SOILS,
+        CellType::AntiSand => &[GREEN],
+        CellType::Water => &WATERS,
+        CellType::Wood => &WOODS,
+        CellType::Tree => &TREES,
+        CellType::Leaf => &LEAVES,
+        CellType::Seed => &[SEED],
+        CellType::Grass => &GRASSES,
+        CellType::Moss => &MOSSES,
+        CellType::Snow => &SNOWS,
+        CellType::Vapour => &[VAPOUR],
+        CellType::Fire => &FIRES,
+        CellType::Lava => &LAVAS,
+        CellType::Stone => &STONES,
+        CellType::Ore => &ORES,
+    }
+}
+
+pub fn cell_color(cell: CellType, shade: u8) -> Color {
+    let p = palette(cell);
+    return p[shade as usize % p.len()];
+}
+
+/// How much to darken a cell at world (x, y) by to draw planks in wood and
+/// bark on trees
+pub fn pattern(cell: CellType, x: i32, y: i32) -> f32 {
+    match cell {
+        // Rows of planks with staggered joints
+        CellType::Wood if y.rem_euclid(5) == 0 || (x + y.div_euclid(5) * 7).rem_euclid(16) == 0 => 0.8,
+        CellType::Tree if (x + y.div_euclid(4)).rem_euclid(3) == 0 => 0.85,
+        _ => 1.0,
+    }
+}
+
+/// An image of the ground kept up to date a chunk at a time: only chunks
+/// whose cells or light have changed since the last paint are repainted,
+/// and only the rectangles that were are uploaded
+pub struct Canvas {
+    pub image: Image,
+    /// Ground and light versions of each chunk as last painted
+    seen: Vec<(u32, u32)>,
+    /// What the whole image was last painted with. If any of them change,
+    /// everything needs repainting.
+    tint: [u8; 3],
+    view_x: i32,
+    origin: i32,
+    /// Rectangles painted since the last upload, as x, y, width, height
+    pub dirty: Vec<(i32, i32, i32, i32)>,
+    /// Draw planks and bark on wood and trees
+    pub patterns: bool,
+}
+
+impl Canvas {
+    pub fn new(w: usize, h: usize) -> Canvas {
+        Canvas {
+            image: Image::gen_image_color(w as u16, h as u16, BLANK),
+            seen: Vec::new(),
+            tint: [0; 3],
+            view_x: 0,
+            origin: 0,
+            dirty: Vec::new(),
+            patterns: true,
+        }
+    }
+
+    pub fn paint(&mut self, ground: &Ground, lights: &LightMap, tint: Color, view_x: i32) {
+        // Tints too close to tell apart on screen don't need a repaint
+        let q = [(tint.r * 255.0) as u8, (tint.g * 255.0) as u8, (tint.b * 255.0) as u8];
+        let tint = Color::from_rgba(q[0], q[1], q[2], 255);
+        let w = self.image.width as i32;
+        let h = self.image.height as i32;
+        let n = ground.versions.len();
+        if q != self.tint || view_x != self.view_x || ground.origin != self.origin || self.seen.len() != n {
+            self.tint = q;
+            self.view_x = view_x;
+            self.origin = ground.origin;
+            self.seen = (0..n).map(|c| (ground.versions[c], lights.versions[c])).collect();
+            self.paint_rect(ground, lights, tint, view_x, (0, 0, w, h));
+            self.dirty.clear();
+            self.dirty.push((0, 0, w, h));
+            return;
+        }
+
+        // Image x of the window's left edge
+        let left = ground.origin - view_x;
+        let c = CHUNK as i32;
+        for cy in 0..ground.ch {
+            // A rectangle over the chunks of the row that changed
+            let mut span: Option<(i32, i32)> = None;
+            for cx in 0..ground.cw {
+                let i = cy * ground.cw + cx;
+                let now = (ground.versions[i], lights.versions[i]);
+                if self.seen[i] == now {
+                    continue;
+                }
+                self.seen[i] = now;
+                let x0 = (left + cx as i32 * c).max(0);
+                let x1 = (left + cx as i32 * c + c).min(w);
+                if x0 >= x1 {
+                    continue;
+                }
+                let y = cy as i32 * c;
+                self.paint_rect(ground, lights, tint, view_x, (x0, y, x1, (y + c).min(h)));
+                span = Some(span.map_or((x0, x1), |(a, b)| (a.min(x0), b.max(x1))));
+            }
+            if let Some((x0, x1)) = span {
+                let y = cy as i32 * c;
+                if y < h {
+                    self.dirty.push((x0, y, x1 - x0, c.min(h - y)));
+                }
+            }
+        }
+    }
+
+    /// Paints the pixels x0 to x1 across and y0 to y1 down, exclusive, with
+    /// the image's left edge at world x `view_x`
+    fn paint_rect(&mut self, ground: &Ground, lights: &LightMap, tint: Color, view_x: i32, (x0, y0, x1, y1): (i32, i32, i32, i32)) {
+        let w = ground.w;
+        for py in y0.max(0) as usize..(y1.max(0) as usize).min(ground.h) {
+            for px in x0.max(0) as usize..x1.max(0) as usize {
+                let lx = view_x - ground.origin + px as i32;
+                if lx < 0 || lx >= w as i32 {
+                    self.image.set_pixel(px as u32, py as u32, BLANK);
+                    continue;
+                }
+                let i = py * w + lx as usize;
+                let cell = ground.cells[i];
+                let c = cell_color(cell, ground.shade[i]);
+                let p = if self.patterns { pattern(cell, view_x + px as i32, py as i32) } else { 1.0 };
+                let l = lights.light(i, tint);
+                self.image.set_pixel(
+                    px as u32,
+                    py as u32,
+                    Color::new(c.r * l.r * p, c.g * l.g * p, c.b * l.b * p, c.a),
+                );
+            }
+        }
+    }
+
+    /// Sends what's been painted since last time to the texture
+    pub fn upload(&mut self, texture: &Texture2D) {
+        for (x, y, w, h) in self.dirty.drain(..) {
+            if w == self.image.width as i32 && h == self.image.height as i32 {
+                texture.update(&self.image);
+                continue;
+            }
+            let part = self.image.sub_image(Rect::new(x as f32, y as f32, w as f32, h as f32));
+            texture.update_part(&part, x, y, w, h);
+        }
+    }
+}
@@ -1,25 +1,43 @@
 use macroquad::prelude::*;
+use crate::agent::Species;
 
 pub struct Resources {
-    pub dino: Texture2D,
-    pub walk: Texture2D
+    /// Each species' sprite sheet, in the same order
+    pub sheets: Vec<Texture2D>,
 }
 
 impl Resources {
-    pub fn new(dino: Texture2D, walk: Texture2D) -> Self {
+    pub fn new(sheets: Vec<Texture2D>) -> Self {
         Resources {
-            dino,
-            walk
+            sheets
         }
     }
 }
 
 
-pub async fn load_resources() -> Resources {
-    let dino: Texture2D = load_texture("res/dino-Sheet.png").await.unwrap();
-    dino.set_filter(FilterMode::Nearest);
-    let walk: Texture2D = load_texture("res/walk.png").await.unwrap();
-    walk.set_filter(FilterMode::Nearest);
-    let resources = Resources::new(dino, walk);
-    return resources;
+/// Loads each species' sprite sheet, checking its frames fit
+pub async fn load_resources(species: &[Species]) -> Result<Resources, String> {
+    let mut sheets = Vec::new();
+    for s in species {
+        let sheet: Texture2D = load_texture(&s.sheet).await.map_err(|e| format!("{}: {}", s.sheet, e))?;
+        s.fits(sheet.width() as u32, sheet.height() as u32)?;
+        sheet.set_filter(FilterMode::Nearest);
+        sheets.push(sheet);
+    }
+    let resources = Resources::new(sheets);
+    return Ok(resources);
+}
+
+/// Each species' sprite sheet as an image, for putting frames together
+/// without a window
+pub fn load_sheets(species: &[Species]) -> Result<Vec<Image>, String> {
+    let load = |path: &str| {
+        let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        return Image::from_file_with_format(&bytes, Some(ImageFormat::Png)).map_err(|e| format!("{}: {}", path, e));
+    };
+    return species.iter().map(|s| {
+        let sheet = load(&s.sheet)?;
+        s.fits(sheet.width as u32, sheet.height as u32)?;
+        return Ok(sheet);
+    }).collect();
 }
@@ -0,0 +1,181 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use crate::ground::{Ground, CellType, CHUNK};
+use crate::clock::Clock;
+use crate::weather::Weather;
+use crate::maf;
+
+/// Where the save and load hotkeys read and write the world
+pub const SAVE_FILE: &str = "world.pxm";
+
+const MAGIC: &[u8; 4] = b"PXMS";
+const VERSION: u32 = 3;
+
+// Layout, all little endian:
+//   "PXMS", version u32, world x of the first column i32 (from version 2),
+//   width u32, height u32,
+//   clock tick u64, day length u64, water in the clouds u32,
+//   cell ids [u8; w * h], cell data [u8; w * h],
+//   cell shades [u8; w * h] (from version 3)
+//
+// Shades are carried round as cells move, so they're saved with them.
+// Older saves get the shade a cell is given where it comes into being.
+//
+// Chunks paged out of an endless world use the same layout, with the
+// clock and weather left at zero.
+
+/// Encodes columns x0 to x1 of the ground
+fn encode(ground: &Ground, x0: i32, x1: i32, tick: u64, day_length: u64, water: u32) -> Vec<u8> {
+    let w = (x1 - x0) as usize;
+    let mut bytes = Vec::with_capacity(40 + w * ground.h * 3);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&x0.to_le_bytes());
+    bytes.extend_from_slice(&(w as u32).to_le_bytes());
+    bytes.extend_from_slice(&(ground.h as u32).to_le_bytes());
+    bytes.extend_from_slice(&tick.to_le_bytes());
+    bytes.extend_from_slice(&day_length.to_le_bytes());
+    bytes.extend_from_slice(&water.to_le_bytes());
+    for y in 0..ground.h as i32 {
+        bytes.extend((x0..x1).map(|x| ground.get_cell(x, y).id()));
+    }
+    for y in 0..ground.h as i32 {
+        bytes.extend((x0..x1).map(|x| ground.get_data(x, y)));
+    }
+    for y in 0..ground.h as i32 {
+        bytes.extend((x0..x1).map(|x| ground.index(x, y).map_or(0, |i| ground.shade[i])));
+    }
+    return bytes;
+}
+
+pub fn save(path: &str, ground: &Ground, clock: &Clock, weather: &Weather) -> io::Result<()> {
+    let x0 = ground.origin;
+    let bytes = encode(ground, x0, x0 + ground.w as i32, clock.tick, clock.day_length, weather.water);
+    return fs::write(path, bytes);
+}
+
+/// Writes CHUNK columns of the ground, from world x0
+pub fn save_chunk(path: &str, ground: &Ground, x0: i32) -> io::Result<()> {
+    return fs::write(path, encode(ground, x0, x0 + CHUNK as i32, 0, 0, 0));
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "save file is cut short"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        return Ok(head);
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        return Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+    }
+}
+
+/// A stretch of ground read back from a save
+struct Saved {
+    origin: i32,
+    w: usize,
+    h: usize,
+    tick: u64,
+    day_length: u64,
+    water: u32,
+    cells: Vec<CellType>,
+    data: Vec<u8>,
+    /// None in saves from before version 3
+    shade: Option<Vec<u8>>,
+}
+
+impl Saved {
+    /// Shade of the saved cell at index i, or for older saves the one
+    /// set_cell_with would give it
+    fn shade(&self, i: usize, seed: u64) -> u8 {
+        return match &self.shade {
+            Some(shade) => shade[i],
+            None => maf::hash(seed, self.origin + (i % self.w) as i32, (i / self.w) as i32) as u8,
+        };
+    }
+}
+
+fn decode(bytes: &[u8]) -> io::Result<Saved> {
+    let mut r = Reader { bytes };
+    if r.take(4)? != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "not a pixmoosh save"));
+    }
+    let version = r.u32()?;
+    let origin = match version {
+        1 => 0,
+        2 | VERSION => r.u32()? as i32,
+        _ => return Err(Error::new(ErrorKind::InvalidData, format!("unknown save version {}", version))),
+    };
+    let w = r.u32()? as usize;
+    let h = r.u32()? as usize;
+    let tick = r.u64()?;
+    let day_length = r.u64()?;
+    let water = r.u32()?;
+    let ids = r.take(w * h)?;
+    let data = r.take(w * h)?;
+    let shade = if version >= 3 { Some(r.take(w * h)?.to_vec()) } else { None };
+
+    let mut cells = Vec::with_capacity(w * h);
+    for id in ids {
+        match CellType::from_id(*id) {
+            Some(c) => cells.push(c),
+            None => return Err(Error::new(ErrorKind::InvalidData, format!("unknown cell id {}", id))),
+        }
+    }
+    return Ok(Saved { origin, w, h, tick, day_length, water, cells, data: data.to_vec(), shade });
+}
+
+/// Loads a saved world over the current one, which must be the same size
+pub fn load(path: &str, ground: &mut Ground, clock: &mut Clock, weather: &mut Weather) -> io::Result<()> {
+    let saved = decode(&fs::read(path)?)?;
+    if saved.w != ground.w || saved.h != ground.h {
+        return Err(Error::new(ErrorKind::InvalidData,
+            format!("save is {}x{}, world is {}x{}", saved.w, saved.h, ground.w, ground.h)));
+    }
+    ground.origin = saved.origin;
+    for i in 0..ground.shade.len() {
+        ground.shade[i] = saved.shade(i, ground.seed);
+    }
+    ground.cells = saved.cells;
+    ground.data.copy_from_slice(&saved.data);
+    for v in ground.versions.iter_mut() {
+        *v = v.wrapping_add(1);
+    }
+    ground.wake_all();
+    clock.tick = saved.tick;
+    clock.day_length = saved.day_length.max(1);
+    weather.water = saved.water;
+    return Ok(());
+}
+
+/// Reads a paged out chunk back into its place in the ground
+pub fn load_chunk(path: &str, ground: &mut Ground) -> io::Result<()> {
+    let saved = decode(&fs::read(path)?)?;
+    if saved.w != CHUNK || saved.h != ground.h {
+        return Err(Error::new(ErrorKind::InvalidData,
+            format!("chunk is {}x{}, expected {}x{}", saved.w, saved.h, CHUNK, ground.h)));
+    }
+    for y in 0..saved.h {
+        for x in 0..saved.w {
+            let i = y * saved.w + x;
+            let (wx, y) = (saved.origin + x as i32, y as i32);
+            ground.set_cell_with(wx, y, saved.cells[i], saved.data[i]);
+            if let Some(j) = ground.index(wx, y) {
+                ground.shade[j] = saved.shade(i, ground.seed);
+            }
+        }
+    }
+    return Ok(());
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use crate::ground::{Ground, CHUNK};
+use crate::terrain::{self, Preset, PRESETS};
+use crate::caves::STRIP;
+use crate::save;
+
+/// Chunks loaded either side of the screen, so things just off it can
+/// carry on when something's there to keep them going
+pub const MARGIN: i32 = 4;
+
+/// How many chunks either side of the screen, or of an agent, are updated
+const REACH: i32 = 1;
+
+/// Streams an endless world through the ground, which holds a window of
+/// it around the screen. Chunks coming into the window are read back from
+/// disk, or generated if they've never been seen; chunks leaving it are
+/// paged out to disk.
+pub struct Stream {
+    /// Where paged out chunks are kept
+    pub dir: String,
+    preset: &'static Preset,
+    seed: u64,
+    /// The last strip of terrain generated, as neighbouring chunks are
+    /// usually wanted one after another
+    strip: Option<Ground>,
+}
+
+impl Stream {
+    pub fn new(dir: &str, terrain: &str, seed: u64) -> Stream {
+        let preset = match terrain::find(terrain) {
+            Some(preset) => preset,
+            None => {
+                eprintln!("The {} terrain can't go on forever, using {}", terrain, PRESETS[0].name);
+                &PRESETS[0]
+            }
+        };
+        Stream {
+            dir: dir.to_string(),
+            preset,
+            seed,
+            strip: None,
+        }
+    }
+
+    /// Width of ground needed for a screen `view` cells across
+    pub fn window(view: usize) -> usize {
+        return (view.div_ceil(CHUNK) + 1 + MARGIN as usize * 2) * CHUNK;
+    }
+
+    fn path(&self, cx: i32) -> String {
+        return format!("{}/chunk_{}.pxm", self.dir, cx);
+    }
+
+    /// Brings in the column of chunks cx, from disk if it's been paged out
+    /// before and otherwise fresh from the terrain generator
+    fn page_in(&mut self, ground: &mut Ground, cx: i32) {
+        let path = self.path(cx);
+        if fs::metadata(&path).is_ok() {
+            match save::load_chunk(&path, ground) {
+                Ok(()) => return,
+                Err(e) => eprintln!("Couldn't load {}: {}", path, e),
+            }
+        }
+
+        // Caves are carved a strip at a time, so generate the whole strip
+        let x0 = cx * CHUNK as i32;
+        let sx = x0.div_euclid(STRIP) * STRIP;
+        if self.strip.as_ref().map(|s| s.origin) != Some(sx) {
+            let mut strip = Ground::new(STRIP as usize, ground.h);
+            strip.origin = sx;
+            terrain::generate_columns(&mut strip, self.preset, self.seed, sx, sx + STRIP);
+            self.strip = Some(strip);
+        }
+        let strip = self.strip.as_ref().unwrap();
+        for y in 0..ground.h as i32 {
+            for x in x0..x0 + CHUNK as i32 {
+                ground.set_cell_with(x, y, strip.get_cell(x, y), strip.get_data(x, y));
+            }
+        }
+    }
+
+    fn page_out(&self, ground: &Ground, cx: i32) {
+        let path = self.path(cx);
+        let saved = fs::create_dir_all(&self.dir)
+            .and_then(|_| save::save_chunk(&path, ground, cx * CHUNK as i32));
+        if let Err(e) = saved {
+            eprintln!("Couldn't save {}: {}", path, e);
+        }
+    }
+
+    /// Loads every chunk of the window, for a fresh ground
+    pub fn fill(&mut self, ground: &mut Ground) {
+        let first = ground.origin.div_euclid(CHUNK as i32);
+        for i in 0..ground.cw as i32 {
+            self.page_in(ground, first + i);
+        }
+    }
+
+    /// Writes every loaded chunk to disk, keeping them loaded
+    pub fn flush(&self, ground: &Ground) {
+        let first = ground.origin.div_euclid(CHUNK as i32);
+        for i in 0..ground.cw as i32 {
+            self.page_out(ground, first + i);
+        }
+    }
+
+    /// Slides the window to keep the screen, from x0 to x1, in the middle
+    /// of it, then picks the chunks to update: those on or near the screen
+    /// and those with an agent in or near them
+    pub fn update(&mut self, ground: &mut Ground, x0: i32, x1: i32, agents: &[i32]) {
+        let c = CHUNK as i32;
+        let n = ground.cw as i32;
+        let first = ground.origin.div_euclid(c);
+        let want = x0.div_euclid(c) - MARGIN;
+        if want != first {
+            for cx in first..first + n {
+                if cx < want || cx >= want + n {
+                    self.page_out(ground, cx);
+                }
+            }
+            ground.shift(want - first);
+            for cx in want..want + n {
+                if cx < first || cx >= first + n {
+                    self.page_in(ground, cx);
+                }
+            }
+        }
+
+        let left = x0.div_euclid(c) - REACH;
+        let right = (x1 - 1).div_euclid(c) + REACH;
+        for i in 0..ground.cw {
+            let cx = want + i as i32;
+            ground.active[i] = (cx >= left && cx <= right)
+                || agents.iter().any(|a| (a.div_euclid(c) - cx).abs() <= REACH);
+        }
+    }
+}
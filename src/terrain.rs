@@ -0,0 +1,179 @@
+use crate::ground::{Ground, CellType};
+use crate::caves;
+use crate::maf::{fbm, noise, hash, hash_f32};
+
+/// The original world: a flat wooden floor with planks scattered above it
+pub const CLASSIC: &str = "classic";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Biome {
+    Desert,
+    Forest,
+    Lake,
+}
+
+/// Knobs for one style of generated world
+pub struct Preset {
+    pub name: &'static str,
+    /// Average surface height, as a fraction of the world's height from the top
+    pub surface: f32,
+    /// How far hills rise and fall, as a fraction of the world's height
+    pub hills: f32,
+    /// Rough width of a hill, in cells
+    pub scale: f32,
+    /// Biomes to pick between across the world
+    pub biomes: &'static [Biome],
+    /// Rough width of a biome, in cells
+    pub biome_scale: f32,
+    /// Share of the stone left open before caves are smoothed out: 0 for no
+    /// caves, up to about 0.45 for a lot
+    pub caves: f32,
+    /// 0 for no ore, up to about 0.1 for a lot
+    pub ore: f32,
+    /// 0 for no underground water, up to about 0.1 for a lot
+    pub pockets: f32,
+}
+
+pub const PRESETS: [Preset; 4] = [
+    Preset {
+        name: "hills",
+        surface: 0.55,
+        hills: 0.12,
+        scale: 300.0,
+        biomes: &[Biome::Forest, Biome::Desert, Biome::Lake, Biome::Forest],
+        biome_scale: 500.0,
+        caves: 0.3,
+        ore: 0.04,
+        pockets: 0.05,
+    },
+    Preset {
+        name: "flat",
+        surface: 0.7,
+        hills: 0.02,
+        scale: 200.0,
+        biomes: &[Biome::Forest, Biome::Lake],
+        biome_scale: 600.0,
+        caves: 0.25,
+        ore: 0.02,
+        pockets: 0.03,
+    },
+    Preset {
+        name: "islands",
+        surface: 0.65,
+        hills: 0.1,
+        scale: 150.0,
+        biomes: &[Biome::Lake, Biome::Forest, Biome::Lake, Biome::Desert],
+        biome_scale: 250.0,
+        caves: 0.28,
+        ore: 0.03,
+        pockets: 0.02,
+    },
+    Preset {
+        name: "desert",
+        surface: 0.6,
+        hills: 0.15,
+        scale: 250.0,
+        biomes: &[Biome::Desert],
+        biome_scale: 1000.0,
+        caves: 0.35,
+        ore: 0.06,
+        pockets: 0.01,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Preset> {
+    return PRESETS.iter().find(|p| p.name == name);
+}
+
+/// Fills the ground from a preset (or the classic world) by name
+pub fn generate(ground: &mut Ground, name: &str, seed: u64) {
+    match find(name) {
+        Some(preset) => generate_columns(ground, preset, seed, 0, ground.w as i32),
+        None => ground.init(seed),
+    }
+}
+
+/// Biome at column x, and how far into it we are: 0 at its edges, 1 in the middle
+pub fn biome_at(preset: &Preset, seed: u64, x: i32) -> (Biome, f32) {
+    // Wobble the borders so biomes aren't all the same width
+    let warp = (noise(seed.wrapping_add(1), x as f32 / 200.0, 0.5) - 0.5) * preset.biome_scale;
+    let v = (x as f32 + warp) / preset.biome_scale;
+    let t = v - v.floor();
+    let i = hash(seed.wrapping_add(6), v.floor() as i32, 0) as usize % preset.biomes.len();
+    return (preset.biomes[i], t * (1.0 - t) * 4.0);
+}
+
+/// The layers of one column, top down: sky, water, topsoil, then stone
+pub struct Column {
+    pub biome: Biome,
+    /// First row of topsoil
+    pub surface: i32,
+    /// First row of water, if it's above the surface
+    pub water_line: i32,
+    pub top: CellType,
+    /// Rows of topsoil
+    pub depth: i32,
+}
+
+pub fn column(preset: &Preset, seed: u64, x: i32, h: usize) -> Column {
+    let h = h as f32;
+    let (biome, inside) = biome_at(preset, seed, x);
+    let hill = (fbm(seed, x as f32 / preset.scale, 0.5, 4) - 0.5) * 2.0;
+    let land = (h * (preset.surface + preset.hills * hill)) as i32;
+    let detail = fbm(seed.wrapping_add(4), x as f32 / 20.0, 0.5, 2);
+
+    let (surface, water_line) = if biome == Biome::Lake {
+        (land + (h * 0.12 * inside) as i32, land + 2)
+    } else {
+        (land, land)
+    };
+    // Topsoil: what it's made of and how deep it goes
+    let (top, depth) = match biome {
+        Biome::Desert => (CellType::Sand, 10 + (detail * 8.0) as i32),
+        Biome::Forest => (CellType::Soil, 5 + (detail * 5.0) as i32),
+        Biome::Lake => (CellType::Sand, 4 + (detail * 3.0) as i32),
+    };
+    return Column { biome, surface, water_line, top, depth };
+}
+
+/// Generates columns x0 to x1. The world comes out the same whether it's
+/// generated all at once or a piece at a time, as long as the pieces are
+/// cut at caves::STRIP boundaries, as caves are carved a strip at a time.
+pub fn generate_columns(ground: &mut Ground, preset: &Preset, seed: u64, x0: i32, x1: i32) {
+    for x in x0..x1 {
+        let c = column(preset, seed, x, ground.h);
+        for y in 0..ground.h as i32 {
+            let cell = if y < c.surface {
+                if y >= c.water_line { CellType::Water } else { CellType::Empty }
+            } else if y < c.surface + c.depth {
+                if c.biome == Biome::Forest && y == c.surface { CellType::Grass } else { c.top }
+            } else {
+                underground(preset, seed, x, y, c.surface)
+            };
+            ground.set_cell(x, y, cell);
+        }
+
+        // Forests start out with a scattering of seeds
+        if c.biome == Biome::Forest && hash_f32(seed.wrapping_add(5), x, 0) < 0.02 {
+            ground.set_cell(x, c.surface - 1, CellType::Seed);
+        }
+    }
+    caves::carve(ground, preset, seed, x0, x1);
+}
+
+fn underground(preset: &Preset, seed: u64, x: i32, y: i32, surface: i32) -> CellType {
+    let deep = y - surface;
+    if deep > 15 {
+        let p = noise(seed.wrapping_add(3), x as f32 / 30.0, y as f32 / 20.0);
+        if p > 1.0 - preset.pockets {
+            return CellType::Water;
+        }
+    }
+    if deep > 20 {
+        let o = noise(seed.wrapping_add(7), x as f32 / 6.0, y as f32 / 6.0);
+        if o > 1.0 - preset.ore {
+            return CellType::Ore;
+        }
+    }
+    return CellType::Stone;
+}
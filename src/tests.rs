@@ -1,7 +1,26 @@
-use crate::ground::{Ground, CellType};
+use crate::ground::{Ground, CellType, Topology, CHUNK, SLOW};
+use crate::stream::{self, Stream};
+use crate::plant;
+use crate::weather::{Weather, Precip};
+use crate::wind::Wind;
+use crate::clock::{Clock, Pacer, TICK_RATE};
+use crate::save;
+use crate::light::LightMap;
+use crate::render::Canvas;
+use crate::overlay::{self, Overlays};
+use crate::minimap::Minimap;
+use crate::capture::{self, Recorder};
+use crate::agent::{self, Agent, Dir, Job};
+use crate::terrain;
+use crate::resources;
+use crate::maf;
+use crate::caves;
+use crate::config::Config;
+use crate::camera::{Camera, MAX_ZOOM};
 use macroquad::prelude::*;
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
@@ -141,5 +160,1045 @@ mod tests {
 
     }
 
+    fn count(g: &Ground, t: CellType) -> usize {
+        g.cells.iter().filter(|c| **c == t).count()
+    }
+
+    fn soil_bed(w: usize, h: usize) -> Ground {
+        let mut g = Ground::new(w, h);
+        // Cold enough that the water doesn't evaporate from under the plants
+        g.temperature = 0.0;
+        for x in 0..w as i32 {
+            g.set_cell(x, h as i32 - 1, CellType::Soil);
+        }
+        g
+    }
+
+    #[test]
+    fn seed_sprouts_near_water() {
+        let mut g = soil_bed(12, 40);
+        // Water pond walled off from the seed
+        g.set_cell(1, 38, CellType::Water);
+        g.set_cell(3, 38, CellType::Wood);
+        g.set_cell(6, 38, CellType::Seed);
+        for _ in 0..500 {
+            g.update();
+        }
+        assert_eq!(g.get_cell(6, 38), CellType::Tree);
+        assert!(count(&g, CellType::Tree) > 1);
+    }
+
+    #[test]
+    fn seed_stays_dormant_without_water() {
+        let mut g = soil_bed(12, 40);
+        g.set_cell(6, 38, CellType::Seed);
+        for _ in 0..500 {
+            g.update();
+        }
+        assert_eq!(g.get_cell(6, 38), CellType::Seed);
+        assert_eq!(count(&g, CellType::Tree), 0);
+    }
+
+    #[test]
+    fn tree_stops_at_species_height() {
+        let mut g = soil_bed(40, 60);
+        g.set_cell(13, 58, CellType::Wood);
+        g.set_cell(15, 58, CellType::Water);
+        g.set_cell(17, 58, CellType::Wood);
+        g.set_cell_with(20, 58, CellType::Seed, plant::seed_data(2));
+        for _ in 0..2000 {
+            g.update();
+        }
+        let top = (0..60).find(|y| g.get_cell(20, *y) == CellType::Tree).unwrap();
+        assert_eq!(58 - top, plant::SPECIES[2].height as i32 - 1);
+        assert!(count(&g, CellType::Leaf) > 0);
+    }
+
+    #[test]
+    fn tree_withers_without_water() {
+        let mut g = soil_bed(12, 20);
+        for y in 10..19 {
+            g.set_cell_with(6, y, CellType::Tree, plant::pack(0, 0));
+        }
+        for _ in 0..2000 {
+            g.update();
+        }
+        assert_eq!(count(&g, CellType::Tree), 0);
+        assert_eq!(count(&g, CellType::Wood), 9);
+    }
+
+    #[test]
+    fn grass_spreads_over_moist_soil() {
+        let mut g = soil_bed(12, 10);
+        g.set_cell(0, 8, CellType::Water);
+        g.set_cell(1, 8, CellType::Wood);
+        g.set_cell(6, 9, CellType::Grass);
+        for _ in 0..1000 {
+            g.update();
+        }
+        assert!(count(&g, CellType::Grass) > 3);
+    }
+
+    #[test]
+    fn grass_dies_when_buried() {
+        let mut g = soil_bed(12, 10);
+        g.set_cell(0, 8, CellType::Water);
+        g.set_cell(1, 8, CellType::Wood);
+        g.set_cell(6, 9, CellType::Grass);
+        g.set_cell(6, 8, CellType::Sand);
+        g.update();
+        assert_eq!(g.get_cell(6, 9), CellType::Soil);
+    }
+
+    #[test]
+    fn moss_grows_on_wet_wood() {
+        let mut g = Ground::new(12, 10);
+        // Too cold for the water to evaporate
+        g.temperature = 0.0;
+        for x in 0..12 {
+            g.set_cell(x, 9, CellType::Wood);
+        }
+        g.set_cell(5, 8, CellType::Water);
+        for _ in 0..5000 {
+            g.update();
+        }
+        assert!(count(&g, CellType::Moss) > 0);
+    }
+
+    #[test]
+    fn rain_falls_from_clouds() {
+        let mut g = Ground::new(200, 20);
+        let mut weather = Weather::new(1);
+        weather.closed = false;
+        weather.set(Precip::Rain, g.w);
+        for _ in 0..100 {
+            weather.update(&mut g);
+            g.update();
+        }
+        assert!(count(&g, CellType::Water) > 0);
+        assert_eq!(count(&g, CellType::Snow), 0);
+    }
+
+    #[test]
+    fn weather_is_seeded() {
+        let mut a = Ground::new(200, 20);
+        let mut b = Ground::new(200, 20);
+        let mut wa = Weather::new(42);
+        let mut wb = Weather::new(42);
+        wa.water = 1000;
+        wb.water = 1000;
+        wa.set(Precip::Snow, a.w);
+        wb.set(Precip::Snow, b.w);
+        for _ in 0..20 {
+            wa.update(&mut a);
+            wb.update(&mut b);
+        }
+        assert!(count(&a, CellType::Snow) > 0);
+        assert!(a.cells == b.cells);
+    }
+
+    #[test]
+    fn snow_melts_when_warm() {
+        let mut g = Ground::new(4, 4);
+        g.temperature = 20.0;
+        g.set_cell(1, 3, CellType::Snow);
+        for _ in 0..5000 {
+            g.update();
+        }
+        assert_eq!(count(&g, CellType::Snow), 0);
+        // It may well have evaporated since
+        let wet = count(&g, CellType::Water) + count(&g, CellType::Vapour);
+        assert_eq!(wet + g.condensed as usize, 1);
+    }
+
+    fn water_mass(g: &Ground, weather: &Weather) -> usize {
+        count(g, CellType::Water) + count(g, CellType::Vapour)
+            + count(g, CellType::Snow) + weather.water as usize
+    }
+
+    #[test]
+    fn water_evaporates_and_rises() {
+        let mut g = Ground::new(8, 20);
+        g.temperature = 30.0;
+        for x in 0..8 {
+            g.set_cell(x, 19, CellType::Water);
+        }
+        for _ in 0..2000 {
+            g.update();
+        }
+        assert!(count(&g, CellType::Water) < 8);
+        assert!(g.condensed > 0);
+    }
+
+    #[test]
+    fn barely_warm_water_and_snow_hardly_change() {
+        let mut g = Ground::new(8, 4);
+        g.temperature = f32::MIN_POSITIVE;
+        for x in 0..4 {
+            g.set_cell(x, 3, CellType::Water);
+            g.set_cell(x + 4, 3, CellType::Snow);
+        }
+        for _ in 0..200 {
+            g.update();
+        }
+        assert_eq!(count(&g, CellType::Water), 4);
+        assert_eq!(count(&g, CellType::Snow), 4);
+    }
+
+    #[test]
+    fn closed_water_cycle_conserves_water() {
+        let mut g = Ground::new(120, 40);
+        g.temperature = 30.0;
+        for x in 0..120 {
+            g.set_cell(x, 39, CellType::Wood);
+            g.set_cell(x, 38, CellType::Water);
+            g.set_cell(x, 37, CellType::Water);
+        }
+        let mut weather = Weather::new(7);
+        weather.water = 100;
+        weather.set(Precip::Rain, g.w);
+        let start = water_mass(&g, &weather);
+        for _ in 0..3000 {
+            weather.update(&mut g);
+            g.update();
+        }
+        // Anything condensed since the last weather update is still in transit
+        assert_eq!(water_mass(&g, &weather) + g.condensed as usize, start);
+    }
+
+    #[test]
+    fn wind_blows_snow_downwind() {
+        let mut g = Ground::new(40, 30);
+        g.temperature = -5.0;
+        for v in g.wind.field.iter_mut() {
+            *v = vec2(1.0, 0.0);
+        }
+        g.set_cell(5, 0, CellType::Snow);
+        for _ in 0..40 {
+            g.update();
+        }
+        let x = (0..40).find(|x| g.get_cell(*x, 29) == CellType::Snow).unwrap();
+        assert!(x > 20);
+    }
+
+    #[test]
+    fn wind_gusts_are_seeded() {
+        let mut a = Wind::new(200, 100, 3);
+        let mut b = Wind::new(200, 100, 3);
+        for _ in 0..500 {
+            a.update(0.2);
+            b.update(0.2);
+        }
+        assert_eq!(a.field, b.field);
+        assert!(a.field.iter().any(|v| v.x != a.field[0].x));
+    }
+
+    #[test]
+    fn clock_runs_day_and_night() {
+        let mut clock = Clock::new(100);
+        clock.tick = 0;
+        assert_eq!(clock.daylight(), 0.0);
+        for _ in 0..50 {
+            clock.advance();
+        }
+        assert_eq!(clock.daylight(), 1.0);
+        assert_eq!(clock.hh_mm(), (12, 0));
+        for _ in 0..50 {
+            clock.advance();
+        }
+        assert_eq!(clock.day(), 1);
+        assert_eq!(clock.daylight(), 0.0);
+    }
+
+    #[test]
+    fn trees_dont_grow_at_night() {
+        let mut g = soil_bed(12, 20);
+        g.daylight = 0.0;
+        g.set_cell(1, 18, CellType::Water);
+        g.set_cell(3, 18, CellType::Wood);
+        g.set_cell_with(6, 18, CellType::Tree, plant::pack(0, 10));
+        for _ in 0..500 {
+            g.update();
+        }
+        assert_eq!(count(&g, CellType::Tree), 1);
+    }
+
+    #[test]
+    fn save_and_load_world() {
+        let path = std::env::temp_dir().join(format!("pixmoosh-test-{}.pxm", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut g = Ground::new(6, 4);
+        g.set_cell(1, 3, CellType::Sand);
+        g.set_cell_with(2, 3, CellType::Tree, plant::pack(1, 7));
+        let mut clock = Clock::new(100);
+        clock.tick = 1234;
+        let mut weather = Weather::new(1);
+        weather.water = 55;
+        save::save(path, &g, &clock, &weather).unwrap();
+
+        let mut g2 = Ground::new(6, 4);
+        let mut clock2 = Clock::new(10);
+        let mut weather2 = Weather::new(2);
+        save::load(path, &mut g2, &mut clock2, &mut weather2).unwrap();
+        assert!(g2.cells == g.cells);
+        assert_eq!(g2.get_data(2, 3), plant::pack(1, 7));
+        assert_eq!(g2.shade, g.shade);
+        assert_eq!(clock2.tick, 1234);
+        assert_eq!(clock2.day_length, 100);
+        assert_eq!(weather2.water, 55);
+
+        // Shades moved with their cells come back where they were, and
+        // saves from before there were shades get them by position
+        let fresh = g.shade.clone();
+        g.shade.swap(6 * 3 + 1, 6 * 3 + 2);
+        save::save(path, &g, &clock, &weather).unwrap();
+        save::load(path, &mut g2, &mut clock2, &mut weather2).unwrap();
+        assert_eq!(g2.shade, g.shade);
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[4..8].copy_from_slice(&2u32.to_le_bytes());
+        bytes.truncate(bytes.len() - 6 * 4);
+        std::fs::write(path, bytes).unwrap();
+        save::load(path, &mut g2, &mut clock2, &mut weather2).unwrap();
+        assert_eq!(g2.shade[6 * 3 + 1..6 * 3 + 3], fresh[6 * 3 + 1..6 * 3 + 3]);
+
+        let mut wrong_size = Ground::new(5, 4);
+        assert!(save::load(path, &mut wrong_size, &mut clock2, &mut weather2).is_err());
+        let _ = std::fs::remove_file(path);
+    }
+
+    fn wood_block(w: usize, h: usize) -> Ground {
+        let mut g = Ground::new(w, h);
+        for y in h as i32 / 2..h as i32 {
+            for x in 0..w as i32 {
+                g.set_cell(x, y, CellType::Wood);
+            }
+        }
+        g
+    }
+
+    fn light_at(lights: &LightMap, x: usize, y: usize) -> (u8, u8) {
+        (lights.sky[y * lights.w + x], lights.glow[y * lights.w + x])
+    }
+
+    #[test]
+    fn caves_are_dark() {
+        let mut g = wood_block(160, 160);
+        for x in 20..60 {
+            g.set_cell(x, 120, CellType::Empty);
+        }
+        let mut lights = LightMap::new(&g);
+        lights.update(&g);
+        assert_eq!(light_at(&lights, 40, 40), (255, 0));
+        assert_eq!(light_at(&lights, 40, 80), (255, 0));
+        assert_eq!(light_at(&lights, 40, 120), (0, 0));
+
+        // Open a shaft down to the tunnel and daylight gets in
+        for y in 80..120 {
+            g.set_cell(58, y, CellType::Empty);
+        }
+        lights.update(&g);
+        assert!(light_at(&lights, 50, 120).0 > 0);
+    }
+
+    #[test]
+    fn lava_lights_up_caves() {
+        let mut g = wood_block(200, 160);
+        for x in 20..120 {
+            g.set_cell(x, 120, CellType::Empty);
+        }
+        g.set_cell(30, 120, CellType::Lava);
+        let mut lights = LightMap::new(&g);
+        lights.update(&g);
+        assert_eq!(light_at(&lights, 30, 120).1, 220);
+        assert!(light_at(&lights, 40, 120).1 > 0);
+        assert_eq!(light_at(&lights, 110, 120).1, 0);
+        assert_eq!(light_at(&lights, 40, 120).0, 0);
+    }
+
+    #[test]
+    fn canvas_repaints_only_what_changed() {
+        let mut g = wood_block(400, 200);
+        let mut lights = LightMap::new(&g);
+        lights.update(&g);
+        let mut canvas = Canvas::new(400, 200);
+        canvas.paint(&g, &lights, WHITE, 0);
+        assert_eq!(canvas.dirty, vec![(0, 0, 400, 200)]);
+        canvas.dirty.clear();
+        canvas.paint(&g, &lights, WHITE, 0);
+        assert!(canvas.dirty.is_empty());
+
+        let before = canvas.image.get_pixel(40, 150);
+        g.set_cell(40, 150, CellType::Lava);
+        lights.update(&g);
+        canvas.paint(&g, &lights, WHITE, 0);
+        assert!(canvas.image.get_pixel(40, 150) != before);
+        assert!(!canvas.dirty.is_empty());
+        for &(x, y, w, _) in canvas.dirty.iter() {
+            assert!(y >= CHUNK as i32 && x + w <= 2 * CHUNK as i32);
+        }
+
+        // A new tint changes everything
+        canvas.dirty.clear();
+        canvas.paint(&g, &lights, RED, 0);
+        assert_eq!(canvas.dirty, vec![(0, 0, 400, 200)]);
+    }
+
+    #[test]
+    fn overlays_show_what_moved_and_what_it_cost() {
+        let mut g = Ground::new(200, 200);
+        g.set_cell(100, 10, CellType::Sand);
+        g.update();
+        g.update();
+        let y = (0..200).find(|&y| g.get_cell(100, y) == CellType::Sand).unwrap();
+        assert!(y > 10);
+        assert!(g.has_moved(100, y));
+
+        let mut overlays = Overlays::new(200, 200);
+        overlays.paint_moved(&g);
+        assert!(overlays.image.get_pixel(100, y as u32).a > 0.0);
+        assert_eq!(overlays.image.get_pixel(20, 150).a, 0.0);
+
+        // Only the chunk the sand's falling through was updated
+        let c = y as usize / CHUNK * g.cw + 100 / CHUNK;
+        assert!(g.cost[c] > 0);
+        assert_eq!(g.cost.iter().filter(|&&n| n > 0).count(), 1);
+
+        let mut lights = LightMap::new(&g);
+        lights.update(&g);
+        let lines = overlay::describe(&g, &lights, 100, y);
+        assert_eq!(lines[0], format!("Sand at 100, {}", y));
+        assert!(lines[1].ends_with("moved"));
+        assert_eq!(overlay::describe(&g, &lights, -5, 0), vec!["Bedrock at -5, 0"]);
+    }
+
+    #[test]
+    fn minimap_repaints_only_changed_chunks() {
+        let mut g = wood_block(1000, 200);
+        let mut map = Minimap::new(1000, 200);
+        assert_eq!(map.scale, 4);
+        assert_eq!((map.image.width, map.image.height), (250, 50));
+        assert_eq!(map.update(&g), g.versions.len());
+        assert_eq!(map.update(&g), 0);
+        assert!(map.image.get_pixel(10, 45).a > 0.0);
+        assert_eq!(map.image.get_pixel(10, 5).a, 0.0);
+
+        // Filling a block of the sky only touches its chunk
+        for y in 8..16 {
+            for x in 200..208 {
+                g.set_cell(x, y, CellType::Stone);
+            }
+        }
+        assert_eq!(map.update(&g), 1);
+        assert_eq!(map.image.get_pixel(51, 3).a, 1.0);
+
+        let r = map.rect(1280.0, 720.0);
+        assert_eq!(map.to_world(r, r.x + 50.0, r.y + 2.0), Some((200.0, 8.0)));
+        assert_eq!(map.to_world(r, r.x - 1.0, r.y), None);
+    }
+
+    #[test]
+    fn frames_are_put_together_without_a_window() {
+        let g = wood_block(64, 64);
+        let mut lights = LightMap::new(&g);
+        lights.update(&g);
+        let mut canvas = Canvas::new(64, 64);
+        canvas.paint(&g, &lights, WHITE, 0);
+        // A sheet with only the top left pixel of the dino's frame showing
+        let species = agent::builtin();
+        let mut d = Agent::new(0, &species[0], 10.0, 4.0);
+        let src = d.sprite.frame().source_rect;
+        let mut sheet = Image::gen_image_color(256, 256, BLANK);
+        sheet.set_pixel(src.x as u32, src.y as u32, WHITE);
+        let sheets = [sheet];
+
+        let frame = capture::compose(&canvas, &g, &lights, WHITE, std::slice::from_ref(&d), &species, &sheets);
+        assert_eq!(frame.get_pixel(10, 4), WHITE);
+        assert!(frame.get_pixel(11, 4) != WHITE);
+        assert!(frame.bytes.chunks(4).all(|p| p[3] == 255));
+        // Facing west, it's drawn the other way round
+        d.dir = Dir::West;
+        let frame = capture::compose(&canvas, &g, &lights, WHITE, &[d], &species, &sheets);
+        assert_eq!(frame.get_pixel(10 + src.w as u32 - 1, 4), WHITE);
+        // A frame past the edge of the sheet is left out
+        let mut clipped = frame.clone();
+        capture::stamp(&mut clipped, &sheets[0], Rect::new(250.0, 250.0, 16.0, 16.0), 0, 0, false, WHITE);
+        assert_eq!(clipped.bytes, frame.bytes);
+
+        let dir = std::env::temp_dir();
+        let png = dir.join(format!("pixmoosh-{}.png", std::process::id()));
+        let png = png.to_str().unwrap();
+        capture::save_png(png, &frame).unwrap();
+        let read = Image::from_file_with_format(&std::fs::read(png).unwrap(), Some(ImageFormat::Png)).unwrap();
+        assert_eq!(read.bytes, frame.bytes);
+
+        for (ext, magic) in [("gif", &b"GIF89a"[..]), ("apng", &b"acTL"[..])] {
+            let path = dir.join(format!("pixmoosh-{}.{}", std::process::id(), ext));
+            let path = path.to_str().unwrap();
+            let mut r = Recorder::start(path, 64, 64, 0.1).unwrap();
+            r.add(&frame).unwrap();
+            r.add(&frame).unwrap();
+            assert!(r.add(&Image::gen_image_color(8, 8, RED)).is_err());
+            r.finish().unwrap();
+            let bytes = std::fs::read(path).unwrap();
+            assert!(bytes.windows(magic.len()).any(|w| w == magic));
+            let _ = std::fs::remove_file(path);
+        }
+        assert!(Recorder::start("clip.mp4", 64, 64, 0.1).is_err());
+        let _ = std::fs::remove_file(png);
+    }
+
+    #[test]
+    fn species_differ_only_where_they_say() {
+        let species = agent::builtin();
+        let mut g = Ground::new(100, 200);
+        g.wind = Wind::new(100, 200, 0);
+        for x in 0..100 {
+            for y in 150..200 {
+                g.set_cell(x, y, CellType::Stone);
+            }
+        }
+        for (s, sp) in species.iter().enumerate() {
+            // Done digging, they plant as many seeds as their species does
+            let mut a = Agent::new(s, sp, 40.0, 134.0);
+            a.job = Job::Dig;
+            let seeds = (0..10000)
+                .map(|_| a.step(sp, &g, 100, 200))
+                .map(|v| v.iter().filter(|c| c.2 == CellType::Seed).count())
+                .find(|&n| n > 0);
+            assert_eq!(seeds, Some(sp.footprints.plant.len()), "{}", sp.name);
+
+            // Standing about in mid air, only some drift as they fall
+            let mut a = Agent::new(s, sp, 40.0, 20.0);
+            a.job = Job::Idle;
+            a.step(sp, &g, 100, 200);
+            assert!(a.y > 20.0);
+            assert_eq!(a.x < 40.0, sp.drifts, "{}", sp.name);
+        }
+    }
+
+    #[test]
+    fn starving_agents_go_looking_for_food() {
+        let species = agent::builtin();
+        let dino = &species[0];
+        let mut g = Ground::new(100, 100);
+        g.wind = Wind::new(100, 100, 0);
+        for x in 0..100 {
+            for y in 60..100 {
+                g.set_cell(x, y, CellType::Stone);
+            }
+        }
+        // The odds of it stopping or turning are the same every run
+        maf::with_stream(1, || {
+            // Too hungry to stand about or build, it walks on at half speed
+            let mut a = Agent::new(0, dino, 40.0, 43.0);
+            a.job = Job::Idle;
+            a.hunger = agent::STARVING + 1;
+            a.step(dino, &g, 100, 100);
+            assert!(a.job == Job::Walk);
+            let x = a.x;
+            a.job = Job::Build;
+            assert!(a.step(dino, &g, 100, 100).is_empty());
+            assert!(a.job == Job::Walk);
+            assert!(((a.x - x).abs() - dino.speed / 2.0).abs() < 1e-4);
+
+            // Until it finds something to eat
+            let fx = a.x as i32 + dino.foot[0];
+            for x in fx - 2..=fx + 2 {
+                g.set_cell(x, 60, CellType::Grass);
+            }
+            let eaten = a.step(dino, &g, 100, 100);
+            assert!(eaten.iter().any(|c| c.1 == 60 && c.2 == CellType::Soil));
+            assert_eq!(a.hunger, 0);
+            let x = a.x;
+            a.step(dino, &g, 100, 100);
+            assert!(((a.x - x).abs() - dino.speed).abs() < 1e-4);
+        });
+    }
+
+    #[test]
+    fn species_are_loaded_from_files() {
+        let shipped = agent::load(agent::SPECIES_DIR).unwrap();
+        let names: Vec<&str> = shipped.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["dino", "person"]);
+
+        // A new creature, quicker than the others and twice their size
+        let text = include_str!("../res/species/dino.toml")
+            .replace("name = \"dino\"", "name = \"hare\"")
+            .replace("frame = [16, 16]", "frame = [32, 32]")
+            .replace("speed = 0.2", "speed = 0.5")
+            .replace("foot = [8, 16]", "foot = [16, 32]");
+        let dir = std::env::temp_dir().join(format!("pixmoosh-species-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("hare.toml"), &text).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a species").unwrap();
+        let species = agent::load(dir.to_str().unwrap()).unwrap();
+        assert_eq!(species.len(), 1);
+        let hare = &species[0];
+        assert_eq!((hare.name.as_str(), hare.centre()), ("hare", (16.0, 16.0)));
+
+        let mut g = Ground::new(200, 100);
+        g.wind = Wind::new(200, 100, 0);
+        for x in 0..200 {
+            g.set_cell(x, 60, CellType::Stone);
+        }
+        let mut a = Agent::new(0, hare, 50.0, 28.0);
+        a.step(hare, &g, 200, 100);
+        assert_eq!(a.sprite.frame().dest_size, vec2(32.0, 32.0));
+        assert_eq!((a.x, a.y), (50.5, 28.0));
+
+        // It's twice the size on the same sheet, so its frames don't fit
+        assert!(resources::load_sheets(&species).err().is_some_and(|e| e.contains("hare")));
+        assert!(resources::load_sheets(&agent::builtin()).is_ok());
+
+        std::fs::write(dir.join("hare.toml"), text.replace("dug = 50", "dug = 0")).unwrap();
+        assert!(agent::load(dir.to_str().unwrap()).is_err());
+        std::fs::write(dir.join("hare.toml"), text.replace("count = 5", "count = 5\nwings = 2")).unwrap();
+        assert!(agent::load(dir.to_str().unwrap()).err().is_some_and(|e| e.contains("wings")));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(agent::load(dir.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn pacer_runs_at_a_steady_rate() {
+        let mut p = Pacer::new();
+        let ticks: u32 = (0..30).map(|_| p.ticks(1.0 / 30.0)).sum();
+        assert!((ticks as f32 - TICK_RATE).abs() <= 1.0);
+
+        p.slower();
+        p.slower();
+        assert_eq!(p.speed(), 0.25);
+        let ticks: u32 = (0..120).map(|_| p.ticks(1.0 / 120.0)).sum();
+        assert!((ticks as f32 - TICK_RATE / 4.0).abs() <= 1.0);
+
+        // A long stall doesn't bring on a flood of ticks
+        for _ in 0..10 {
+            p.faster();
+        }
+        assert_eq!(p.speed(), 16.0);
+        assert!(p.ticks(5.0) <= 64);
+    }
+
+    #[test]
+    fn pacer_pauses_and_steps() {
+        let mut p = Pacer::new();
+        p.paused = true;
+        assert_eq!(p.ticks(1.0), 0);
+        p.step();
+        assert_eq!(p.ticks(1.0), 1);
+        assert_eq!(p.ticks(1.0), 0);
+        p.paused = false;
+        assert!(p.ticks(0.5) > 0);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_mouse() {
+        let mut c = Camera::new();
+        c.x = 100.0;
+        let before = c.to_world(300.0, 200.0);
+        c.zoom_by(3, 300.0, 200.0);
+        assert_eq!(c.zoom, 4);
+        assert_eq!(c.to_world(300.0, 200.0), before);
+        assert_eq!(c.to_screen(before.0, before.1), (300.0, 200.0));
+        c.zoom_by(100, 0.0, 0.0);
+        assert_eq!(c.zoom, MAX_ZOOM);
+        c.zoom_by(-100, 0.0, 0.0);
+        assert_eq!(c.zoom, 1);
+    }
+
+    #[test]
+    fn camera_stays_on_the_world() {
+        let mut c = Camera::new();
+        c.zoom = 2;
+        c.pan(-1000.0, 5000.0);
+        c.clamp(0.0, 800.0, 600.0, 800.0, 600.0, false);
+        assert_eq!((c.x, c.y), (0.0, 300.0));
+
+        // An endless world can be panned along forever, but not up and down
+        c.pan(-1000.0, -5000.0);
+        c.clamp(0.0, 800.0, 600.0, 800.0, 600.0, true);
+        assert_eq!((c.x, c.y), (-500.0, 0.0));
+
+        // A world smaller than the screen sits in the middle of it
+        c.zoom = 1;
+        c.clamp(0.0, 400.0, 600.0, 800.0, 600.0, false);
+        assert_eq!(c.x, -200.0);
+    }
+
+    #[test]
+    fn small_worlds_are_scaled_up_to_fit() {
+        let mut c = Camera::new();
+        c.fit(0.0, 400.0, 300.0, 1920.0, 1080.0);
+        assert_eq!(c.zoom, 3);
+        let (x, y) = c.to_screen(0.0, 0.0);
+        let (x1, y1) = c.to_screen(400.0, 300.0);
+        assert_eq!((x, 1920.0 - x1), (360.0, 360.0));
+        assert_eq!((y, 1080.0 - y1), (90.0, 90.0));
+
+        // A world bigger than the window isn't shrunk
+        c.fit(0.0, 4000.0, 3000.0, 1920.0, 1080.0);
+        assert_eq!(c.zoom, 1);
+    }
+
+    #[test]
+    fn world_size_comes_from_the_settings() {
+        let mut config = Config::new(1);
+        assert_eq!(config.width, None);
+        config.set("width", "640");
+        config.set("height", "0");
+        assert_eq!((config.width, config.height), (Some(640), None));
+    }
+
+    #[test]
+    fn cells_keep_their_shade_as_they_fall() {
+        let mut g = Ground::new(40, 20);
+        g.seed = 3;
+        for x in 0..40 {
+            g.set_cell(x, 0, CellType::Sand);
+        }
+        let shades: Vec<u8> = g.shade[..40].to_vec();
+        assert!(shades.iter().any(|&s| s != shades[0]));
+        for _ in 0..30 {
+            g.update();
+        }
+        // Nothing piles up from a single row, so every grain fell straight down
+        for x in 0..40 {
+            assert_eq!(g.get_cell(x, 19), CellType::Sand);
+            assert_eq!(g.shade[19 * 40 + x as usize], shades[x as usize]);
+        }
+    }
+
+    #[test]
+    fn fire_burns_wood_away() {
+        let mut g = Ground::new(20, 3);
+        for x in 0..20 {
+            g.set_cell(x, 2, CellType::Wood);
+        }
+        g.set_cell(0, 1, CellType::Fire);
+        for _ in 0..3000 {
+            g.update();
+        }
+        assert_eq!(count(&g, CellType::Wood), 0);
+        assert_eq!(count(&g, CellType::Fire), 0);
+    }
+
+    #[test]
+    fn terrain_is_seeded() {
+        let mut a = Ground::new(300, 200);
+        let mut b = Ground::new(300, 200);
+        terrain::generate(&mut a, "hills", 3);
+        terrain::generate(&mut b, "hills", 3);
+        assert!(a.cells == b.cells);
+        terrain::generate(&mut b, "hills", 4);
+        assert!(a.cells != b.cells);
+    }
+
+    #[test]
+    fn terrain_generates_a_piece_at_a_time() {
+        let preset = terrain::find("islands").unwrap();
+        let w = caves::STRIP * 2;
+        let mut whole = Ground::new(w as usize, 200);
+        let mut pieces = Ground::new(w as usize, 200);
+        terrain::generate_columns(&mut whole, preset, 9, 0, w);
+        terrain::generate_columns(&mut pieces, preset, 9, caves::STRIP, w);
+        terrain::generate_columns(&mut pieces, preset, 9, 0, caves::STRIP);
+        assert!(whole.cells == pieces.cells);
+    }
+
+    #[test]
+    fn desert_is_sand_over_stone() {
+        // Without caves, whose stone lined shafts come out anywhere
+        let desert = terrain::Preset { caves: 0.0, ..*terrain::find("desert").unwrap() };
+        let mut g = Ground::new(200, 200);
+        terrain::generate_columns(&mut g, &desert, 1, 0, 200);
+        for x in 0..200 {
+            let mut y = 0;
+            while g.get_cell(x, y) == CellType::Empty {
+                y += 1;
+            }
+            assert_eq!(g.get_cell(x, y), CellType::Sand);
+        }
+        assert!(count(&g, CellType::Stone) > 0);
+    }
+
+    #[test]
+    fn terrain_comes_out_the_same_a_strip_at_a_time() {
+        let hills = terrain::find("hills").unwrap();
+        let w = caves::STRIP as usize * 3;
+        let mut whole = Ground::new(w, 150);
+        whole.origin = -caves::STRIP;
+        terrain::generate_columns(&mut whole, hills, 7, -caves::STRIP, caves::STRIP * 2);
+        let mut pieces = Ground::new(w, 150);
+        pieces.origin = -caves::STRIP;
+        for x0 in [caves::STRIP, -caves::STRIP, 0] {
+            terrain::generate_columns(&mut pieces, hills, 7, x0, x0 + caves::STRIP);
+        }
+        assert!(pieces.cells == whole.cells);
+        assert_eq!(pieces.data, whole.data);
+        assert!(count(&whole, CellType::Empty) < w * 150);
+    }
+
+    #[test]
+    fn unknown_terrain_is_classic() {
+        let mut g = Ground::new(100, 100);
+        terrain::generate(&mut g, terrain::CLASSIC, 1);
+        assert_eq!(g.get_cell(50, 99), CellType::Wood);
+        assert_eq!(count(&g, CellType::Stone), 0);
+        // Its planks are placed by the seed too
+        let mut same = Ground::new(100, 100);
+        terrain::generate(&mut same, terrain::CLASSIC, 1);
+        assert!(same.cells == g.cells);
+        let mut other = Ground::new(100, 100);
+        terrain::generate(&mut other, terrain::CLASSIC, 2);
+        assert!(other.cells != g.cells);
+    }
+
+    #[test]
+    fn tiny_classic_world_is_all_topsoil() {
+        let mut g = Ground::new(8, 4);
+        terrain::generate(&mut g, terrain::CLASSIC, 1);
+        assert_eq!(g.get_cell(0, 0), CellType::Empty);
+        assert_eq!(count(&g, CellType::Soil), 8 * 3);
+    }
+
+    #[test]
+    fn every_cave_reaches_the_surface() {
+        let mut g = Ground::new(600, 300);
+        terrain::generate(&mut g, "desert", 5);
+        let mut reached = vec![false; g.w * g.h];
+        let mut todo: Vec<(i32, i32)> = (0..g.w as i32).map(|x| (x, 0)).collect();
+        while let Some((x, y)) = todo.pop() {
+            if g.get_cell(x, y) != CellType::Empty || reached[y as usize * g.w + x as usize] {
+                continue;
+            }
+            reached[y as usize * g.w + x as usize] = true;
+            todo.extend_from_slice(&[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+        }
+        let mut caves = 0;
+        for x in 0..g.w as i32 {
+            let c = terrain::column(terrain::find("desert").unwrap(), 5, x, g.h);
+            for y in c.surface..g.h as i32 {
+                if g.get_cell(x, y) == CellType::Empty {
+                    assert!(reached[y as usize * g.w + x as usize], "cave at {} {} is cut off", x, y);
+                    caves += 1;
+                }
+            }
+        }
+        assert!(caves > 500);
+        assert!(count(&g, CellType::Ore) > 0);
+    }
+
+    #[test]
+    fn sand_falls_round_wrapped_edges() {
+        let mut g = Ground::new(4, 2);
+        g.set_cell(0, 0, CellType::Sand);
+        g.set_cell(0, 1, CellType::Sand);
+        g.set_cell(1, 1, CellType::Wood);
+        g.update();
+        assert_eq!(g.get_cell(0, 0), CellType::Sand);
+
+        g.topology = Topology::WrapX;
+        g.wake_all();
+        g.update();
+        assert_eq!(g.get_cell(0, 0), CellType::Empty);
+        assert_eq!(g.get_cell(3, 1), CellType::Sand);
+        assert_eq!(g.get_cell(-1, 1), CellType::Sand);
+        assert_eq!(g.get_cell(0, -1), CellType::Bedrock);
+    }
+
+    /// A busy wrapped world with narrow chunks at the seams, run on the
+    /// given number of threads
+    fn busy_world(threads: usize) -> Ground {
+        let mut g = Ground::new(CHUNK * 3 + 20, 150);
+        g.topology = Topology::Wrap;
+        g.seed = 5;
+        terrain::generate(&mut g, "hills", 5);
+        for x in 0..g.w as i32 {
+            g.set_cell(x, 2, if x % 3 == 0 { CellType::Water } else { CellType::Sand });
+            if x % 17 == 0 {
+                g.set_cell(x, 4, CellType::Lava);
+            }
+        }
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| {
+            for _ in 0..80 {
+                g.update();
+            }
+        });
+        return g;
+    }
+
+    #[test]
+    fn updates_dont_depend_on_the_number_of_threads() {
+        let one = busy_world(1);
+        let many = busy_world(4);
+        assert!(one.cells == many.cells);
+        assert!(one.data == many.data);
+        assert_eq!(one.condensed, many.condensed);
+    }
+
+    #[test]
+    fn water_falls_out_of_the_bottom_of_a_torus() {
+        let mut g = Ground::new(1, 3);
+        g.topology = Topology::Wrap;
+        g.set_cell(0, 2, CellType::Water);
+        g.update();
+        assert_eq!(g.get_cell(0, 0), CellType::Water);
+        assert!(g.set_cell(0, 5, CellType::Sand));
+        assert_eq!(g.get_cell(0, 2), CellType::Sand);
+    }
+
+    /// An endless world's window onto hills, paging to a fresh directory
+    fn endless(name: &str) -> (Ground, Stream) {
+        let dir = std::env::temp_dir().join(format!("pixmoosh-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut g = Ground::new(Stream::window(100), 120);
+        g.topology = Topology::Infinite;
+        g.origin = -stream::MARGIN * CHUNK as i32;
+        let mut s = Stream::new(dir.to_str().unwrap(), "hills", 3);
+        s.fill(&mut g);
+        return (g, s);
+    }
+
+    #[test]
+    fn endless_world_matches_generated_one() {
+        let (g, _) = endless("generated");
+        let mut whole = Ground::new(caves::STRIP as usize * 3, 120);
+        whole.origin = -caves::STRIP;
+        terrain::generate_columns(&mut whole, terrain::find("hills").unwrap(), 3, -caves::STRIP, caves::STRIP * 2);
+        for y in 0..120 {
+            for x in g.origin..g.origin + g.w as i32 {
+                assert_eq!(g.get_cell(x, y), whole.get_cell(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_page_out_and_back_in() {
+        let (mut g, mut s) = endless("paging");
+        g.set_cell(10, 5, CellType::Wood);
+        // As if it had moved there from somewhere else
+        let i = g.index(10, 5).unwrap();
+        g.shade[i] = g.shade[i].wrapping_add(1);
+        let shade = g.shade[i];
+        s.update(&mut g, 5000, 5100, &[]);
+        assert_eq!(g.get_cell(10, 5), CellType::Bedrock);
+        assert_eq!(g.origin, (5000 / CHUNK as i32 - stream::MARGIN) * CHUNK as i32);
+        s.update(&mut g, 0, 100, &[]);
+        assert_eq!(g.get_cell(10, 5), CellType::Wood);
+        assert_eq!(g.shade[g.index(10, 5).unwrap()], shade);
+        let _ = std::fs::remove_dir_all(&s.dir);
+    }
 
+    #[test]
+    fn only_chunks_near_the_screen_or_agents_update() {
+        let (mut g, mut s) = endless("active");
+        s.update(&mut g, 0, 100, &[]);
+        g.set_cell(330, 5, CellType::Sand);
+        g.update();
+        assert_eq!(g.get_cell(330, 5), CellType::Sand);
+        s.update(&mut g, 0, 100, &[330]);
+        g.update();
+        assert_eq!(g.get_cell(330, 5), CellType::Empty);
+        let _ = std::fs::remove_dir_all(&s.dir);
+    }
+
+    fn is_settled(g: &Ground) -> bool {
+        return g.dirty.iter().all(|r| r.is_empty());
+    }
+
+    #[test]
+    fn settled_ground_sleeps() {
+        let mut g = Ground::new(200, 150);
+        g.temperature = 0.0;
+        for x in 0..200 {
+            g.set_cell(x, 149, CellType::Stone);
+        }
+        // Water only settles once it fills its container's floor
+        for y in 130..149 {
+            g.set_cell(140, y, CellType::Wood);
+            g.set_cell(160, y, CellType::Wood);
+        }
+        for y in 0..38 {
+            g.set_cell(100, y, CellType::Sand);
+            g.set_cell(150, y, CellType::Water);
+        }
+        for _ in 0..1000 {
+            g.update();
+        }
+        assert!(is_settled(&g));
+        assert_eq!(count(&g, CellType::Sand), 38);
+        assert_eq!(count(&g, CellType::Water), 38);
+
+        // Digging under the pile wakes it up again
+        g.set_cell(100, 148, CellType::Empty);
+        assert!(!is_settled(&g));
+        g.update();
+        assert!(!is_settled(&g));
+    }
+
+    #[test]
+    fn warm_ponds_and_bare_soil_sleep_between_slow_ticks() {
+        let mut g = Ground::new(200, 60);
+        // Warm, if only just, so the water could evaporate
+        g.temperature = 0.01;
+        for x in 0..200 {
+            g.set_cell(x, 59, CellType::Stone);
+            // Too far from the water for grass to take
+            let top = if x < 60 { CellType::Soil } else if x < 140 { CellType::Stone } else { CellType::Water };
+            g.set_cell(x, 58, top);
+        }
+        for _ in 0..100 {
+            g.update();
+        }
+        let mut asleep = 0;
+        for _ in 0..SLOW * 5 {
+            g.update();
+            if is_settled(&g) {
+                asleep += 1;
+            }
+        }
+        assert!(asleep > SLOW * 4);
+        assert_eq!(count(&g, CellType::Soil), 60);
+    }
+
+    #[test]
+    fn water_doesnt_swap_into_solids() {
+        let mut g = Ground::new(8, 3);
+        g.temperature = 0.0;
+        for x in 0..8 {
+            g.set_cell(x, 2, CellType::Stone);
+        }
+        // Hemmed in by wood on the left, it used to trade places with the
+        // sand on its right
+        g.set_cell(1, 1, CellType::Wood);
+        g.set_cell(2, 1, CellType::Water);
+        g.set_cell(3, 1, CellType::Sand);
+        // ...and water with water on both sides never stopped sloshing
+        g.set_cell(4, 1, CellType::Wood);
+        for x in 5..8 {
+            g.set_cell(x, 1, CellType::Water);
+        }
+        for _ in 0..50 {
+            g.update();
+        }
+        assert_eq!(g.get_cell(2, 1), CellType::Water);
+        assert_eq!(g.get_cell(3, 1), CellType::Sand);
+        assert!(is_settled(&g));
+    }
+
+    #[test]
+    fn ground_wakes_up_across_chunks() {
+        let mut g = Ground::new(CHUNK * 2, 10);
+        for x in 0..CHUNK as i32 * 2 {
+            g.set_cell(x, 9, CellType::Wood);
+        }
+        g.set_cell(CHUNK as i32, 8, CellType::Sand);
+        g.set_cell(CHUNK as i32, 7, CellType::Sand);
+        for _ in 0..10 {
+            g.update();
+        }
+        // The top grain slid off into the chunk to the left
+        assert_eq!(g.get_cell(CHUNK as i32 - 1, 8), CellType::Sand);
+        assert!(is_settled(&g));
+    }
 }
@@ -0,0 +1,169 @@
+use macroquad::prelude::*;
+use crate::ground::{Ground, CellType};
+use crate::maf::Rng;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Precip {
+    Clear,
+    Rain,
+    Snow,
+}
+
+/// Water the clouds need to hold before they'll start raining by themselves
+const RAIN_WATER: u32 = 200;
+
+pub struct Cloud {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+}
+
+pub struct Weather {
+    rng: Rng,
+    pub precip: Precip,
+    /// Let the weather change by itself
+    pub auto: bool,
+    /// How hard it's coming down, 0 to 1
+    pub intensity: f32,
+    /// Prevailing wind, in cells per tick
+    pub wind: f32,
+    /// Over the ground's window, so the weather follows the screen round an
+    /// endless world
+    pub clouds: Vec<Cloud>,
+    /// Ticks until the weather changes
+    timer: u32,
+    /// Rain and snow only fall from the water the clouds hold
+    pub closed: bool,
+    /// Cells' worth of water held in the clouds
+    pub water: u32,
+}
+
+impl Weather {
+    pub fn new(seed: u64) -> Weather {
+        Weather {
+            rng: Rng::new(seed),
+            precip: Precip::Clear,
+            auto: true,
+            intensity: 0.0,
+            wind: 0.0,
+            clouds: Vec::new(),
+            timer: 0,
+            closed: true,
+            water: 0,
+        }
+    }
+
+    /// Fix the weather (from the UI or config), turning off auto changes
+    pub fn set(&mut self, precip: Precip, w: usize) {
+        self.auto = false;
+        self.change(precip, w);
+    }
+
+    fn change(&mut self, precip: Precip, w: usize) {
+        self.precip = precip;
+        self.intensity = 0.3 + self.rng.next_f32() * 0.7;
+        self.wind = (self.rng.next_f32() - 0.5) * 0.6;
+        self.timer = self.rng.range(600, 3000) as u32;
+
+        let num = match precip {
+            Precip::Clear => self.rng.range(0, 4),
+            _ => 3 + (self.intensity * 6.0) as i32,
+        };
+        self.clouds.clear();
+        for _ in 0..num {
+            self.clouds.push(Cloud {
+                x: self.rng.range(0, w as i32) as f32,
+                y: self.rng.range(4, 30) as f32,
+                w: self.rng.range(40, 160) as f32,
+            });
+        }
+    }
+
+    fn ambient(&self) -> f32 {
+        match self.precip {
+            Precip::Clear => 15.0,
+            Precip::Rain => 8.0,
+            Precip::Snow => -5.0,
+        }
+    }
+
+    pub fn update(&mut self, ground: &mut Ground) {
+        let w = ground.w as f32;
+
+        if self.timer == 0 {
+            let next = if !self.auto {
+                self.precip
+            } else if self.closed && self.water < RAIN_WATER {
+                Precip::Clear
+            } else {
+                match self.rng.range(0, 6) {
+                    0..=2 => Precip::Clear,
+                    3 | 4 => Precip::Rain,
+                    _ => Precip::Snow,
+                }
+            };
+            self.change(next, ground.w);
+        }
+        self.timer -= 1;
+
+        self.water += ground.condensed;
+        ground.condensed = 0;
+
+        // Temperature eases toward what the weather calls for
+        ground.temperature += (self.ambient() - ground.temperature) * 0.002;
+
+        ground.wind.update(self.wind);
+        for c in self.clouds.iter_mut() {
+            c.x += ground.wind.at(c.x as i32, c.y as i32).x;
+            if c.x > w {
+                c.x -= w + c.w;
+            }
+            if c.x < -c.w {
+                c.x += w + c.w;
+            }
+        }
+
+        let cell = match self.precip {
+            Precip::Clear => return,
+            Precip::Rain => CellType::Water,
+            Precip::Snow => CellType::Snow,
+        };
+        // Odds per column under a cloud per tick, 1 in `chance`
+        let chance = (60.0 / self.intensity) as i32;
+        for c in self.clouds.iter() {
+            for i in 0..c.w as i32 {
+                if !self.rng.one_in(chance) {
+                    continue;
+                }
+                if self.closed && self.water == 0 {
+                    return;
+                }
+                let x = ground.origin + c.x as i32 + i;
+                if ground.get_cell(x, 0) != CellType::Empty {
+                    continue;
+                }
+                ground.set_cell(x, 0, cell);
+                if self.closed {
+                    self.water -= 1;
+                }
+            }
+        }
+    }
+
+    /// Draws the clouds, shifted `dx` across the screen
+    pub fn draw(&self, dx: f32) {
+        let shade = match self.precip {
+            Precip::Clear => Color::new(1.0, 1.0, 1.0, 0.5),
+            Precip::Rain => Color::new(0.5, 0.55, 0.6, 0.7),
+            Precip::Snow => Color::new(0.85, 0.85, 0.9, 0.7),
+        };
+        for c in self.clouds.iter() {
+            let r = 10.0;
+            let x = c.x + dx;
+            draw_rectangle(x + r, c.y - r, c.w - r * 2.0, r * 2.0, shade);
+            draw_circle(x + r, c.y, r, shade);
+            draw_circle(x + c.w - r, c.y, r, shade);
+            draw_circle(x + c.w * 0.4, c.y - r, r * 1.4, shade);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use macroquad::prelude::*;
+use crate::maf::Rng;
+
+/// Size in cells of each square of the wind field
+pub const WIND_CELL: usize = 32;
+
+struct Gust {
+    x: f32,
+    y: f32,
+    r: f32,
+    v: Vec2,
+    life: u32,
+}
+
+/// A coarse grid of wind velocities laid over the ground, roughly in cells
+/// per tick. Made of the weather's prevailing wind, a slow ripple, and
+/// gusts that blow through and die out.
+pub struct Wind {
+    rng: Rng,
+    pub w: usize,
+    pub h: usize,
+    pub field: Vec<Vec2>,
+    gusts: Vec<Gust>,
+    t: u32,
+}
+
+impl Wind {
+    pub fn new(w: usize, h: usize, seed: u64) -> Wind {
+        let w = w.div_ceil(WIND_CELL);
+        let h = h.div_ceil(WIND_CELL);
+        Wind {
+            rng: Rng::new(seed),
+            w,
+            h,
+            field: vec![Vec2::ZERO; w * h],
+            gusts: Vec::new(),
+            t: 0,
+        }
+    }
+
+    pub fn at(&self, x: i32, y: i32) -> Vec2 {
+        let cx = (x.max(0) as usize / WIND_CELL).min(self.w - 1);
+        let cy = (y.max(0) as usize / WIND_CELL).min(self.h - 1);
+        return self.field[cy * self.w + cx];
+    }
+
+    pub fn update(&mut self, prevailing: f32) {
+        self.t += 1;
+
+        if self.rng.one_in(120) {
+            let along = if prevailing < 0.0 { -1.0 } else { 1.0 };
+            let sign = if self.rng.one_in(4) { -along } else { along };
+            self.gusts.push(Gust {
+                x: self.rng.range(0, (self.w * WIND_CELL) as i32) as f32,
+                y: self.rng.range(0, (self.h * WIND_CELL) as i32) as f32,
+                r: self.rng.range(60, 200) as f32,
+                v: vec2(
+                    sign * (0.5 + self.rng.next_f32()),
+                    (self.rng.next_f32() - 0.5) * 0.6),
+                life: self.rng.range(100, 400) as u32,
+            });
+        }
+        for g in self.gusts.iter_mut() {
+            g.x += g.v.x * 2.0;
+            g.y += g.v.y * 2.0;
+            g.life -= 1;
+        }
+        self.gusts.retain(|g| g.life > 0);
+
+        let t = self.t as f32 * 0.01;
+        for cy in 0..self.h {
+            for cx in 0..self.w {
+                let px = ((cx * WIND_CELL) + WIND_CELL / 2) as f32;
+                let py = ((cy * WIND_CELL) + WIND_CELL / 2) as f32;
+                let ripple = (t + cx as f32 * 0.3 + cy as f32 * 0.1).sin() * 0.15;
+                let mut v = vec2(prevailing + ripple, 0.0);
+                for g in self.gusts.iter() {
+                    let d = vec2(px - g.x, py - g.y).length() / g.r;
+                    if d < 1.0 {
+                        // Fade in and out over the gust's life, and toward its edge
+                        let fade = (g.life as f32 / 50.0).min(1.0);
+                        v += g.v * (1.0 - d) * fade;
+                    }
+                }
+                self.field[cy * self.w + cx] = v.clamp_length_max(1.0);
+            }
+        }
+    }
+
+    /// Draws the field as arrows, shifted `dx` across the screen
+    pub fn draw(&self, dx: f32) {
+        let half = WIND_CELL as f32 / 2.0;
+        for cy in 0..self.h {
+            for cx in 0..self.w {
+                let v = self.field[cy * self.w + cx];
+                let x = (cx * WIND_CELL) as f32 + half + dx;
+                let y = (cy * WIND_CELL) as f32 + half;
+                let end = vec2(x, y) + v * half;
+                draw_line(x, y, end.x, end.y, 1.0, YELLOW);
+                draw_circle(end.x, end.y, 1.5, YELLOW);
+            }
+        }
+    }
+}